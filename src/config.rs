@@ -1,16 +1,55 @@
-use std::{convert::TryFrom, fs::read_to_string, path::Path, str::from_utf8};
+use std::{
+    convert::TryFrom,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::from_utf8,
+    time::Duration,
+};
 
-use log::{debug, error, info, warn};
+use log::{debug, error, warn, Level};
 use regex::Regex;
 
-use crate::util::prettify_option;
+use crate::util::{prettify_option, RateLimitedLog};
 
 mod config_error;
+pub use config_error::{ConfigError, Location};
+
+mod add_rule;
+pub use add_rule::{add_rule, RuleType};
+
+mod check_config;
+pub use check_config::check_config_files;
+
+mod config_to_toml;
+use config_to_toml::config_to_toml;
+
+mod desktop_entry;
+use desktop_entry::DesktopEntries;
+
+mod icon_names;
+
+mod icon_sets;
+use icon_sets::{apply_default_labels, apply_icon_set, category_icon};
+
+mod template;
+use template::Template;
+pub use template::Window;
+
+mod lint_config;
+pub use lint_config::{lint_config, Severity};
 
 mod parse_content_to_config;
-use parse_content_to_config::parse_content_to_config;
+use parse_content_to_config::{parse_content_to_config, parse_table_to_config};
+
+mod read_config_files;
+pub use read_config_files::{conf_d_dir, default_config_paths};
+use read_config_files::{read_config_files, ConfigFiles};
 
-pub const DEFAULT_MATCH_CONFIG: &'static [u8] = include_bytes!("../default_config.toml");
+pub const DEFAULT_MATCH_CONFIG: &[u8] = include_bytes!("../default_config.toml");
+
+/// Messages logged for every window event, like windows without a match, are repeated
+/// at most once an hour for the same window
+static REPEATED_LOG: RateLimitedLog = RateLimitedLog::new(Duration::from_secs(60 * 60));
 
 #[derive(Clone, Debug)]
 pub enum Pattern {
@@ -21,8 +60,8 @@ pub enum Pattern {
 impl PartialEq for Pattern {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (Self::String(r0), Self::Regex(l0)) => l0.to_string() == r0.to_string(),
-            (Self::Regex(l0), Self::String(r0)) => l0.to_string() == r0.to_string(),
+            (Self::String(r0), Self::Regex(l0)) => l0.to_string() == *r0,
+            (Self::Regex(l0), Self::String(r0)) => l0.to_string() == *r0,
             (Self::Regex(l0), Self::Regex(r0)) => l0.to_string() == r0.to_string(),
             (Self::String(l0), Self::String(r0)) => l0 == r0,
         }
@@ -50,76 +89,638 @@ pub enum Match {
     Exact { pattern: String, value: String },
 }
 
+impl Match {
+    /// Check if a window with the given app name and title is matched
+    pub fn is_match(&self, exact_name: &str, generic_name: Option<&String>) -> bool {
+        match self {
+            Match::Generic { pattern, .. } => match (pattern, generic_name) {
+                (Pattern::Regex(r), Some(generic_name)) => r.is_match(generic_name),
+                (Pattern::String(p), Some(generic_name)) => {
+                    generic_name.to_lowercase().contains(&p.to_lowercase())
+                }
+                (_, None) => false,
+            },
+            Match::Exact { pattern, .. } => exact_name == pattern,
+        }
+    }
+
+    /// Get the pattern as it is written in the config
+    pub fn pattern_key(&self) -> String {
+        match self {
+            Match::Generic {
+                pattern: Pattern::Regex(r),
+                ..
+            } => format!("/{r}/"),
+            Match::Generic {
+                pattern: Pattern::String(p),
+                ..
+            } => p.clone(),
+            Match::Exact { pattern, .. } => pattern.clone(),
+        }
+    }
+
+    pub fn value(&self) -> &String {
+        match self {
+            Match::Generic { value, .. } | Match::Exact { value, .. } => value,
+        }
+    }
+
+    pub fn value_mut(&mut self) -> &mut String {
+        match self {
+            Match::Generic { value, .. } | Match::Exact { value, .. } => value,
+        }
+    }
+}
+
+/// Replaces all icons of a workspace when all of its apps and icons are present
+#[derive(Clone, Debug, PartialEq)]
+pub struct Combination {
+    /// Patterns that each have to match at least one window, values are unused
+    pub apps: Vec<Match>,
+    /// Icons that each have to be present in the workspace
+    pub icons: Vec<String>,
+    /// Only apply when every window is covered by `apps` or `icons`
+    pub exclusive: bool,
+    pub value: String,
+}
+
+impl Combination {
+    /// Check if the combination applies to the matched (exact_name, generic_name, icon) windows
+    pub fn is_match(&self, windows: &[(&str, Option<&String>, (usize, String))]) -> bool {
+        let app_matches = |m: &Match| {
+            windows
+                .iter()
+                .any(|(exact_name, generic_name, _)| m.is_match(exact_name, *generic_name))
+        };
+        let icon_matches = |icon: &String| windows.iter().any(|(_, _, (_, i))| i == icon);
+
+        if !self.apps.iter().all(app_matches) || !self.icons.iter().all(icon_matches) {
+            return false;
+        }
+
+        !self.exclusive
+            || windows.iter().all(|(exact_name, generic_name, (_, icon))| {
+                self.apps
+                    .iter()
+                    .any(|m| m.is_match(exact_name, *generic_name))
+                    || self.icons.contains(icon)
+            })
+    }
+}
+
+/// Selects a workspace by its number or name
+#[derive(Clone, Debug, PartialEq)]
+pub enum WorkspaceSelector {
+    Num(i32),
+    Name(String),
+}
+
+impl WorkspaceSelector {
+    /// Check if the workspace is selected, a name also selects a numbered workspace
+    /// named like `3:notes`
+    pub fn is_match(&self, num: Option<i32>, name: &str) -> bool {
+        match self {
+            WorkspaceSelector::Num(n) => num == Some(*n),
+            WorkspaceSelector::Name(n) => {
+                name == n
+                    || name.split_once(':').is_some_and(|(prefix, rest)| {
+                        prefix.trim().parse::<i32>().is_ok() && rest.trim() == n
+                    })
+            }
+        }
+    }
+}
+
+impl From<&str> for WorkspaceSelector {
+    fn from(value: &str) -> Self {
+        match value.parse() {
+            Ok(num) => WorkspaceSelector::Num(num),
+            Err(_) => WorkspaceSelector::Name(value.to_string()),
+        }
+    }
+}
+
+/// A fixed icon or label always shown in front of the icons of a workspace.
+/// Selected by number, as sworkstyle replaces the rest of the name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkspaceStyle {
+    pub num: i32,
+    pub value: String,
+    /// Also show the value when there are no windows in the workspace
+    pub show_empty: bool,
+}
+
+/// What to do when a workspace got renamed by someone other than sworkstyle
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RenamePolicy {
+    /// Rename the workspace again
+    #[default]
+    Overwrite,
+    /// Keep the new name as label in front of the icons
+    Respect,
+    /// Stop renaming the workspace
+    BackOff,
+}
+
+/// Icons used by the default rules
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IconSet {
+    /// The icons of the default config
+    #[default]
+    FontAwesome,
+    NerdFont,
+    Emoji,
+    /// Short text labels
+    Ascii,
+}
+
+impl IconSet {
+    /// Name as used in the config
+    pub fn name(&self) -> &'static str {
+        match self {
+            IconSet::FontAwesome => "fontawesome",
+            IconSet::NerdFont => "nerdfont",
+            IconSet::Emoji => "emoji",
+            IconSet::Ascii => "ascii",
+        }
+    }
+}
+
+/// What is shown for each window in a workspace name
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DisplayMode {
+    #[default]
+    Icon,
+    /// The label of the matching rule, or the icon when it has no label
+    Label,
+    /// The icon followed by the label of the matching rule
+    Both,
+}
+
+/// Options of a matching rule besides its value
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RuleStyle {
+    /// Text shown instead of or next to the icon
+    pub label: Option<String>,
+    /// Colors, font and size are only used with markup enabled
+    pub color: Option<String>,
+    pub font: Option<String>,
+    pub size: Option<String>,
+}
+
+/// Stages tried in order for a window no rule matched, before using the fallback.
+/// All are off by default, so the fallback is used like before.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FallbackChain {
+    /// Try the rules again with the names from the app's `.desktop` entry
+    pub desktop_entry: bool,
+    /// An icon for the categories of the `.desktop` entry
+    pub category: bool,
+    /// The first letter of the app name, circled
+    pub first_letter: bool,
+}
+
+/// Where a matching comes from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    /// The built-in default config
+    Default,
+    /// A config file
+    File(PathBuf),
+    /// Config content that has not been read from a file
+    Content,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "default config"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Content => write!(f, "config"),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub matchings: Vec<Match>,
     pub fallback: Option<String>,
     pub separator: Option<String>,
+    /// Maximum amount of icons shown per workspace
+    pub max_icons: Option<usize>,
+    /// Maximum display width of the icons shown per workspace
+    pub max_width: Option<usize>,
+    /// Windows that are left out of the workspace name, values are unused
+    pub ignore: Vec<Match>,
+    /// Leave out all floating windows
+    pub ignore_floating: bool,
+    /// Leave out windows with a width or height smaller than (width, height)
+    pub ignore_smaller_than: Option<(i32, i32)>,
+    /// Workspace level rules, evaluated in order after matching every window
+    pub combinations: Vec<Combination>,
+    /// Fixed values per workspace
+    pub workspaces: Vec<WorkspaceStyle>,
+    /// Workspaces that are never renamed
+    pub exclude_workspaces: Vec<WorkspaceSelector>,
+    /// Outputs of which the workspaces are never renamed
+    pub exclude_outputs: Vec<String>,
+    pub on_foreign_rename: RenamePolicy,
+    /// Icons used by the default rules
+    pub icon_set: IconSet,
+    pub display: DisplayMode,
+    /// Output pango markup, needed for the styles of rules
+    pub markup: bool,
+    pub fallback_chain: FallbackChain,
+    /// Append the rules of the default config
+    pub use_defaults: bool,
+    /// Patterns of default rules that should not be appended
+    pub disable_defaults: Vec<String>,
+    /// Files the config has been read from
+    pub files: Vec<PathBuf>,
+    /// Where each matching comes from, in the same order as `matchings`
+    pub sources: Vec<Source>,
+    /// Options of each matching, in the same order as `matchings`
+    pub styles: Vec<RuleStyle>,
+    /// Parsed values of the matchings in the same order, followed by the fallback
+    templates: Vec<Template>,
+    /// Used by the fallback chain, empty until loaded with `load_desktop_entries`
+    desktop_entries: DesktopEntries,
 }
 
 impl Config {
     pub fn new<P: AsRef<Path>>(config_path: &Option<P>) -> Config {
-        if let Some(config_path) = config_path {
-            match read_to_string(&config_path) {
-                Ok(content) => return Config::from(content),
-                Err(e) => {
-                    debug!(
-                        "Could not create config from path: {:?} {e}",
-                        config_path.as_ref()
-                    )
-                }
+        Config::with_layers(config_path.as_slice())
+    }
+
+    /// Load the config layers that exist, using the default config when there are none or
+    /// when they are invalid
+    pub fn with_layers<P: AsRef<Path>>(config_paths: &[P]) -> Config {
+        if config_paths.is_empty() {
+            warn!("Default config could not have been found")
+        } else if !config_paths.iter().any(|p| p.as_ref().exists()) {
+            debug!(
+                "Could not create config from paths: {:?}",
+                config_paths
+                    .iter()
+                    .map(|p| p.as_ref())
+                    .collect::<Vec<&Path>>()
+            )
+        } else {
+            match Config::load_layers(config_paths) {
+                Ok(config) => return config,
+                Err(e) => error!("Invalid config format: {}", e),
             }
+        }
+
+        Config::default()
+    }
+
+    /// Like `with_layers` but fails when existing config files can not be loaded
+    pub fn try_with_layers<P: AsRef<Path>>(config_paths: &[P]) -> Result<Config, ConfigError> {
+        if config_paths.iter().any(|p| p.as_ref().exists()) {
+            Config::load_layers(config_paths)
         } else {
-            warn!("Default config could not have been found")
+            Ok(Config::with_layers(config_paths))
         }
+    }
+
+    /// Load a config file with its includes and drop-in files enriching it with the default config
+    pub fn load<P: AsRef<Path>>(config_path: P) -> Result<Config, ConfigError> {
+        Config::load_layers(&[config_path])
+    }
+
+    /// Load multiple config files on top of each other, earlier files take precedence.
+    /// Files that don't exist are skipped.
+    pub fn load_layers<P: AsRef<Path>>(config_paths: &[P]) -> Result<Config, ConfigError> {
+        let mut files = ConfigFiles::default();
+        for path in config_paths.iter().map(|p| p.as_ref()) {
+            if config_paths.len() > 1 && !path.exists() {
+                continue;
+            }
+            read_config_files(path, &mut files)?;
+        }
+
+        let mut config = parse_table_to_config(&files.table)?;
+        config.sources = config
+            .matchings
+            .iter()
+            .map(|m| match files.matching_sources.get(&m.pattern_key()) {
+                Some(file) => Source::File(file.clone()),
+                None => Source::Content,
+            })
+            .collect();
+        config.files = files.files;
+        Ok(Config::with_defaults(config))
+    }
 
-        return Config::default();
-    }
-
-    pub fn fetch_icon(&self, exact_name: &String, generic_name: Option<&String>) -> String {
-        for m in &self.matchings {
-            match m {
-                Match::Generic { pattern, value } => {
-                    if let Some(generic_name) = &generic_name {
-                        match pattern {
-                            Pattern::Regex(r) => {
-                                if r.is_match(generic_name) {
-                                    return value.clone();
-                                }
-                            }
-                            Pattern::String(p) => {
-                                if generic_name.to_lowercase().contains(&p.to_lowercase()) {
-                                    return value.clone();
-                                }
-                            }
-                        }
-                    }
+    /// Parse a string to a config enriching it with the default config
+    pub fn parse(content: &str) -> Result<Config, ConfigError> {
+        let mut config = parse_content_to_config(content)?;
+        config.sources = vec![Source::Content; config.matchings.len()];
+        Ok(Config::with_defaults(config))
+    }
+
+    fn with_defaults(mut user_config: Config) -> Config {
+        let mut default = Config::default();
+        apply_icon_set(&mut default, user_config.icon_set);
+        apply_default_labels(&mut default);
+        if user_config.use_defaults {
+            for pattern in &user_config.disable_defaults {
+                if !default
+                    .matchings
+                    .iter()
+                    .any(|m| m.pattern_key() == *pattern)
+                {
+                    warn!("Disabled default rule \"{pattern}\" does not exist");
                 }
-                Match::Exact { pattern, value } => {
-                    if exact_name == pattern {
-                        return value.clone();
-                    }
+            }
+            user_config
+                .sources
+                .resize(user_config.matchings.len(), Source::Content);
+            user_config
+                .styles
+                .resize(user_config.matchings.len(), RuleStyle::default());
+            for (m, style) in default.matchings.into_iter().zip(default.styles) {
+                if user_config.disable_defaults.contains(&m.pattern_key()) {
+                    continue;
                 }
+                user_config.matchings.push(m);
+                user_config.styles.push(style);
+                user_config.sources.push(Source::Default);
             }
         }
 
-        warn!(
-            "No match for \"{}\" with title \"{}\"",
-            exact_name,
-            prettify_option(generic_name),
+        if user_config.separator.is_none() {
+            user_config.separator = default.separator
+        }
+
+        if user_config.max_icons.is_none() {
+            user_config.max_icons = default.max_icons
+        }
+
+        if user_config.max_width.is_none() {
+            user_config.max_width = default.max_width
+        }
+
+        user_config.ignore.append(&mut default.ignore);
+
+        user_config.combinations.append(&mut default.combinations);
+        user_config.workspaces.append(&mut default.workspaces);
+        user_config
+            .exclude_workspaces
+            .append(&mut default.exclude_workspaces);
+        user_config
+            .exclude_outputs
+            .append(&mut default.exclude_outputs);
+
+        if user_config.ignore_smaller_than.is_none() {
+            user_config.ignore_smaller_than = default.ignore_smaller_than
+        }
+
+        if user_config.fallback.is_none() {
+            warn!(
+                "No fallback set using default: {}",
+                prettify_option(default.fallback.as_ref())
+            );
+            user_config.fallback = default.fallback
+        }
+
+        user_config.parse_templates();
+        user_config
+    }
+
+    pub fn fetch_icon(&self, exact_name: &str, generic_name: Option<&String>) -> String {
+        let window = Window {
+            app_id: Some(exact_name),
+            class: None,
+            title: generic_name.map(|t| t.as_str()),
+        };
+        self.fetch_icon_with_priority(&window).1
+    }
+
+    /// Fetch the rendered icon of a window together with its priority, being the index of
+    /// the matching rule. Lower is more important, the fallback has the lowest priority.
+    pub fn fetch_icon_with_priority(&self, window: &Window) -> (usize, String) {
+        let exact_name = window.exact_name().unwrap_or_default();
+        let generic_name = window.title.map(String::from);
+        for (i, m) in self.matchings.iter().enumerate() {
+            if m.is_match(exact_name, generic_name.as_ref()) {
+                return (i, self.render_value(i, window));
+            }
+        }
+
+        self.fetch_fallback(window)
+    }
+
+    /// Read the desktop entries of the installed apps when the fallback chain uses them.
+    /// Done when loading the config instead of for every window, as it reads many files.
+    pub fn load_desktop_entries(&mut self) {
+        let chain = self.fallback_chain;
+        self.desktop_entries = if chain.desktop_entry || chain.category || chain.first_letter {
+            DesktopEntries::load()
+        } else {
+            DesktopEntries::default()
+        };
+    }
+
+    /// Go through the fallback chain for a window no rule matched. Rules matched through
+    /// the desktop entry keep their priority, the other stages get the fallback priority.
+    pub fn fetch_fallback(&self, window: &Window) -> (usize, String) {
+        let chain = &self.fallback_chain;
+        let exact_name = window.exact_name().unwrap_or_default();
+        let entry = match exact_name {
+            "" => None,
+            exact_name => self.desktop_entries.find(exact_name),
+        };
+        let generic_name = window.title.map(String::from);
+
+        if let Some(entry) = entry.filter(|_| chain.desktop_entry) {
+            for (i, m) in self.matchings.iter().enumerate() {
+                if entry
+                    .names()
+                    .any(|name| m.is_match(name, generic_name.as_ref()))
+                {
+                    debug!("Matched \"{exact_name}\" through its desktop entry");
+                    return (i, self.render_value(i, window));
+                }
+            }
+        }
+
+        if let Some(icon) = entry
+            .filter(|_| chain.category)
+            .and_then(|entry| category_icon(self.icon_set, &entry.categories))
+        {
+            return (usize::MAX, icon);
+        }
+
+        if chain.first_letter {
+            // The entry name is what users know the app by, else `Nautilus` of
+            // `org.gnome.Nautilus`
+            let name = entry
+                .and_then(|entry| entry.name.as_deref())
+                .unwrap_or(exact_name.rsplit('.').next().unwrap_or_default());
+            if let Some(letter) = name.chars().find(|c| c.is_alphanumeric()) {
+                return (usize::MAX, self.styled_letter(letter));
+            }
+        }
+
+        // Reported once per app, titles change too often to tell windows apart
+        REPEATED_LOG.log(
+            Level::Warn,
+            &format!("no match {exact_name}"),
+            &format!(
+                "No match for \"{}\" with title \"{}\"",
+                exact_name,
+                prettify_option(generic_name.as_ref()),
+            ),
         );
 
-        self.fallback()
+        (usize::MAX, self.fallback(window))
+    }
+
+    /// A letter circled like `Ⓝ`, or as capital for the ascii icon set
+    fn styled_letter(&self, letter: char) -> String {
+        let letter = letter.to_uppercase().next().unwrap_or(letter);
+        let circled = match letter {
+            'A'..='Z' => char::from_u32(0x24B6 + (letter as u32 - 'A' as u32)),
+            '1'..='9' => char::from_u32(0x2460 + (letter as u32 - '1' as u32)),
+            '0' => Some('⓪'),
+            _ => None,
+        };
+        match circled {
+            Some(circled) if self.icon_set != IconSet::Ascii => circled.to_string(),
+            _ => letter.to_string(),
+        }
+    }
+
+    /// Serialize the config to toml, including the appended default rules
+    pub fn to_toml(&self) -> String {
+        config_to_toml(self)
+    }
+
+    /// Get the rules tried in order for a window together with their source and whether
+    /// they matched, ending at the first matching rule
+    pub fn explain(
+        &self,
+        exact_name: &str,
+        generic_name: Option<&String>,
+    ) -> Vec<(&Match, &Source, bool)> {
+        let mut tried = vec![];
+        for (i, m) in self.matchings.iter().enumerate() {
+            let matched = m.is_match(exact_name, generic_name);
+            tried.push((m, self.sources.get(i).unwrap_or(&Source::Content), matched));
+            if matched {
+                break;
+            }
+        }
+        tried
+    }
+
+    /// Parse the values of the matchings and the fallback, so they don't have to be parsed
+    /// for every window
+    fn parse_templates(&mut self) {
+        self.templates = self
+            .matchings
+            .iter()
+            .map(|m| match m {
+                Match::Generic {
+                    pattern: Pattern::Regex(regex),
+                    value,
+                } => Template::parse(value, regex.captures_len()),
+                m => Template::parse(m.value(), 0),
+            })
+            .chain(self.fallback.as_deref().map(|f| Template::parse(f, 0)))
+            .collect();
     }
 
-    pub fn fallback(&self) -> String {
+    /// Fill in the window fields and capture groups used in the value of the rule at `index`,
+    /// `usize::MAX` being the fallback
+    pub fn render_value(&self, index: usize, window: &Window) -> String {
+        let template = match index {
+            usize::MAX => self.templates.get(self.matchings.len()),
+            index => self.templates.get(index),
+        };
+        let Some(template) = template else {
+            return String::new();
+        };
+        let captures = match self.matchings.get(index) {
+            Some(Match::Generic {
+                pattern: Pattern::Regex(regex),
+                ..
+            }) => window.title.and_then(|title| regex.captures(title)),
+            _ => None,
+        };
+        template.render(window, captures.as_ref())
+    }
+
+    /// Get what is shown for a window that got `icon` from the rule at `index`
+    pub fn display_value(&self, index: usize, icon: &str) -> String {
+        let label = self.styles.get(index).and_then(|s| s.label.as_deref());
+        match (self.display, label) {
+            (DisplayMode::Label, Some(label)) => label.to_string(),
+            (DisplayMode::Both, Some(label)) if !icon.is_empty() => format!("{icon} {label}"),
+            (DisplayMode::Both, Some(label)) => label.to_string(),
+            _ => icon.to_string(),
+        }
+    }
+
+    /// Get the value of the first combination applying to the matched windows of a workspace
+    pub fn combine(&self, windows: &[(&str, Option<&String>, (usize, String))]) -> Option<&String> {
+        if windows.is_empty() {
+            return None;
+        }
+
+        self.combinations
+            .iter()
+            .find(|c| c.is_match(windows))
+            .map(|c| &c.value)
+    }
+
+    /// Get the fixed style of a workspace
+    pub fn workspace_style(&self, num: Option<i32>) -> Option<&WorkspaceStyle> {
+        self.workspaces.iter().find(|w| Some(w.num) == num)
+    }
+
+    /// Check if a workspace should never be renamed
+    pub fn is_workspace_excluded(
+        &self,
+        num: Option<i32>,
+        name: &str,
+        output: Option<&str>,
+    ) -> bool {
+        self.exclude_workspaces
+            .iter()
+            .any(|w| w.is_match(num, name))
+            || output.is_some_and(|output| self.exclude_outputs.iter().any(|o| o == output))
+    }
+
+    /// Check if a window should be left out of the workspace name
+    pub fn is_ignored(&self, exact_name: &str, generic_name: Option<&String>) -> bool {
+        self.ignore
+            .iter()
+            .any(|m| m.is_match(exact_name, generic_name))
+    }
+
+    /// The fallback for a window no rule matched, with the window fields filled in
+    pub fn fallback(&self, window: &Window) -> String {
+        // Logged once per app, not only for the first app using the fallback
+        let exact_name = window.exact_name().unwrap_or_default();
+        let key = format!("fallback:{exact_name}");
         match &self.fallback {
             Some(fallback) => {
-                info!("Using fallback: {}", fallback);
-                fallback.clone()
+                REPEATED_LOG.log(
+                    Level::Info,
+                    &key,
+                    &format!("Using fallback for \"{}\": {}", exact_name, fallback),
+                );
+                self.render_value(usize::MAX, window)
             }
             None => {
-                warn!("No fallback set using empty string");
+                REPEATED_LOG.log(
+                    Level::Warn,
+                    &key,
+                    &format!("No fallback set for \"{}\" using empty string", exact_name),
+                );
                 String::from("")
             }
         }
@@ -127,32 +728,14 @@ impl Config {
 }
 
 impl<S: Into<String>> From<S> for Config {
-    /// Parse a string to a config enriching it with the default config
+    /// Parse a string to a config enriching it with the default config,
+    /// using the default config when it is invalid
     fn from(value: S) -> Self {
-        let value = value.into();
-        let mut default = Config::default();
-
-        match parse_content_to_config(&value) {
-            Ok(mut user_config) => {
-                user_config.matchings.append(&mut default.matchings);
-
-                if user_config.separator.is_none() {
-                    user_config.separator = default.separator
-                }
-
-                if user_config.fallback.is_none() {
-                    warn!(
-                        "No fallback set using default: {}",
-                        prettify_option(default.fallback.as_ref())
-                    );
-                    user_config.fallback = default.fallback
-                }
-
-                user_config
-            }
+        match Config::parse(&value.into()) {
+            Ok(config) => config,
             Err(e) => {
                 error!("Invalid config format: {}", e);
-                return default;
+                Config::default()
             }
         }
     }
@@ -161,7 +744,10 @@ impl<S: Into<String>> From<S> for Config {
 impl Default for Config {
     fn default() -> Self {
         let default_config_content = from_utf8(DEFAULT_MATCH_CONFIG).unwrap().to_string();
-        return parse_content_to_config(&default_config_content).unwrap();
+        let mut config = parse_content_to_config(&default_config_content).unwrap();
+        config.sources = vec![Source::Default; config.matchings.len()];
+        config.parse_templates();
+        config
     }
 }
 
@@ -171,6 +757,12 @@ fn test_default() {
     assert_eq!(config.fallback.unwrap(), "")
 }
 
+#[test]
+fn test_parse_invalid() {
+    assert!(Config::parse("[matching]\na = ").is_err());
+    assert!(Config::parse("[matching]\na = 'b'").is_ok());
+}
+
 #[test]
 fn test_from_string() {
     let config = Config::from(
@@ -183,9 +775,337 @@ fn test_from_string() {
     ",
     );
 
-    assert_eq!(config.fallback(), "c");
+    assert_eq!(config.fallback(&Window::default()), "c");
+    assert!(!config.is_ignored("a", None));
     assert_eq!(
         config.fetch_icon(&String::from("application"), Some(&String::from("a title"))),
         "d"
     );
 }
+
+#[test]
+fn test_ignore() {
+    let config = Config::from(
+        "
+    ignore = ['pinentry', '/Picture-in-Picture/', { pattern = 'notification', type = 'generic' }]
+    ignore_floating = true
+    ignore_smaller_than = [200, 100]
+    [matching]
+    ",
+    );
+
+    assert!(config.is_ignored("pinentry", None));
+    assert!(config.is_ignored("firefox", Some(&String::from("Picture-in-Picture"))));
+    assert!(config.is_ignored("dunst", Some(&String::from("A Notification"))));
+    assert!(!config.is_ignored("firefox", Some(&String::from("Mozilla Firefox"))));
+    assert!(config.ignore_floating);
+    assert_eq!(config.ignore_smaller_than, Some((200, 100)));
+
+    for size in [
+        "[200, 'a']",
+        "[200.5, 100]",
+        "[200, 3000000000]",
+        "[-1, 100]",
+    ] {
+        let content = format!("ignore_smaller_than = {size}\n[matching]");
+        assert!(Config::parse(&content).is_err(), "{size}");
+    }
+}
+
+#[test]
+fn test_combine() {
+    let config = Config::from(
+        "
+    [matching]
+    'Code' = 'c'
+    'Alacritty' = 'a'
+    'firefox' = 'f'
+
+    [[combine]]
+    apps = ['Code', 'Alacritty']
+    icons = ['f']
+    value = 'dev'
+
+    [[combine]]
+    apps = ['/youtube/']
+    exclusive = true
+    value = 'tv'
+    ",
+    );
+
+    let window = |exact_name, title: &'static str| {
+        (
+            exact_name,
+            title.to_string(),
+            config.fetch_icon_with_priority(&Window {
+                app_id: Some(exact_name),
+                class: None,
+                title: Some(title),
+            }),
+        )
+    };
+    let combine = |windows: Vec<(&'static str, String, (usize, String))>| {
+        let windows: Vec<(&str, Option<&String>, (usize, String))> = windows
+            .iter()
+            .map(|(e, g, i)| (*e, Some(g), i.clone()))
+            .collect();
+        config.combine(&windows).cloned()
+    };
+
+    let dev = vec![
+        window("Code", "main.rs"),
+        window("Alacritty", "bash"),
+        window("firefox", "docs"),
+    ];
+    assert_eq!(combine(dev), Some("dev".to_string()));
+    let no_dev = vec![window("Code", "main.rs"), window("firefox", "docs")];
+    assert_eq!(combine(no_dev), None);
+    let tv = vec![window("firefox", "youtube")];
+    assert_eq!(combine(tv), Some("tv".to_string()));
+    let no_tv = vec![window("firefox", "youtube"), window("Code", "main.rs")];
+    assert_eq!(combine(no_tv), None);
+}
+
+#[test]
+fn test_workspace_style() {
+    let config = Config::from(
+        "
+    [matching]
+
+    [workspaces]
+    1 = 'web'
+    9 = { value = 'chat', show_empty = true }
+    ",
+    );
+
+    assert_eq!(config.workspace_style(Some(1)).unwrap().value, "web");
+    assert!(!config.workspace_style(Some(1)).unwrap().show_empty);
+    assert!(config.workspace_style(Some(9)).unwrap().show_empty);
+    assert!(config.workspace_style(Some(2)).is_none());
+    assert!(config.workspace_style(None).is_none());
+    // The rest of the name is replaced by sworkstyle, so it can't select a workspace
+    assert!(Config::parse("[matching]\n[workspaces]\n'music' = 'm'").is_err());
+}
+
+#[test]
+fn test_exclude() {
+    let config = Config::from(
+        "
+    exclude_workspaces = [9, 'notes']
+    exclude_outputs = ['HDMI-A-1']
+    [matching]
+    ",
+    );
+
+    assert!(config.is_workspace_excluded(Some(9), "9", None));
+    assert!(config.is_workspace_excluded(None, "notes", Some("eDP-1")));
+    assert!(config.is_workspace_excluded(Some(3), "3:notes", None));
+    assert!(config.is_workspace_excluded(Some(3), "3: notes", None));
+    assert!(!config.is_workspace_excluded(Some(3), "3:notes and more", None));
+    assert!(!config.is_workspace_excluded(None, "notes:3", None));
+    assert!(config.is_workspace_excluded(Some(2), "2", Some("HDMI-A-1")));
+    assert!(!config.is_workspace_excluded(Some(2), "2", Some("eDP-1")));
+}
+
+#[test]
+fn test_disable_defaults() {
+    let config = Config::from(
+        "
+    disable_defaults = ['firefox', '/npm/']
+    [matching]
+    ",
+    );
+    let default = Config::default();
+    assert_eq!(config.matchings.len(), default.matchings.len() - 2);
+    assert!(!config
+        .matchings
+        .iter()
+        .any(|m| m.pattern_key() == "firefox" || m.pattern_key() == "/npm/"));
+
+    let config = Config::from(
+        "
+    use_defaults = false
+    [matching]
+    a = 'b'
+    ",
+    );
+    assert_eq!(config.matchings.len(), 1);
+}
+
+#[test]
+fn test_explain() {
+    let config = Config::from(
+        "
+    [matching]
+    'firefox' = 'A'
+    '/term/' = 'B'
+    ",
+    );
+
+    let tried = config.explain("kitty", Some(&"terminal".to_string()));
+    assert_eq!(tried.len(), 2);
+    assert!(!tried[0].2);
+    assert_eq!(tried[1].0.value(), "B");
+    assert_eq!(*tried[1].1, Source::Content);
+    assert!(tried[1].2);
+
+    let tried = config.explain("unknown-app", None);
+    assert_eq!(tried.len(), config.matchings.len());
+    assert!(tried.iter().all(|(_, _, matched)| !matched));
+}
+
+#[test]
+fn test_display_value() {
+    let config = Config::from(
+        "
+    display = 'both'
+    fallback = 'F'
+
+    [matching]
+    'foot' = { value = 'A', label = 'term' }
+    'firefox' = 'B'
+    ",
+    );
+
+    let shown = |config: &Config, app_id| {
+        let (index, icon) = config.fetch_icon_with_priority(&Window {
+            app_id: Some(app_id),
+            ..Window::default()
+        });
+        config.display_value(index, &icon)
+    };
+    assert_eq!(shown(&config, "foot"), "A term");
+    assert_eq!(shown(&config, "firefox"), "B");
+    assert_eq!(shown(&config, "unknown"), "F");
+
+    let config = Config::from("display = 'label'\n[matching]");
+    assert_eq!(shown(&config, "firefox"), "web");
+}
+
+#[test]
+fn test_fallback_template() {
+    let config = Config::from("fallback = '{app_id:8}'\n[matching]");
+    let window = Window {
+        app_id: Some("org.example.Editor"),
+        class: None,
+        title: Some("notes.txt"),
+    };
+    assert_eq!(config.fetch_icon_with_priority(&window).1, "org.exa…");
+
+    let config = Config::from("fallback = '{title:12}'\n[matching]");
+    let window = Window {
+        title: Some("\tA rather\nlong title"),
+        ..window
+    };
+    assert_eq!(config.fallback(&window), "A rather lo…");
+
+    // Not a placeholder, so shown as written
+    let config = Config::from("fallback = '{name}'\n[matching]");
+    assert_eq!(config.fallback(&window), "{name}");
+}
+
+#[test]
+fn test_fallback_chain() {
+    let mut config = Config::from("fallback = 'F'\n[matching]\n'firefox' = 'A'");
+    config.fallback_chain.first_letter = true;
+    let window = |app_id| Window {
+        app_id: Some(app_id),
+        ..Window::default()
+    };
+    // Names no desktop entry is found for
+    assert_eq!(
+        config.fetch_fallback(&window("org.example.sworkstyle")).1,
+        "Ⓢ"
+    );
+    assert_eq!(config.fetch_fallback(&window("7sworkstyle")).1, "⑦");
+    assert_eq!(config.fetch_fallback(&window("_")).1, "F");
+
+    config.icon_set = IconSet::Ascii;
+    assert_eq!(config.fetch_fallback(&window("sworkstyle")).1, "S");
+
+    config.fallback_chain.first_letter = false;
+    assert_eq!(config.fetch_fallback(&window("sworkstyle")).1, "F");
+}
+
+#[test]
+fn test_fallback_chain_desktop_entries() {
+    let dir = crate::util::TestDir::new("chain");
+    dir.write(
+        "firefox-esr.desktop",
+        "[Desktop Entry]\nName=Firefox ESR\nIcon=firefox\nCategories=Network;WebBrowser;\n",
+    );
+    dir.write(
+        "org.example.Browser.desktop",
+        "[Desktop Entry]\nName=Browser\nCategories=Network;WebBrowser;\n",
+    );
+    let desktop_entries = desktop_entry::DesktopEntries::load_from(std::slice::from_ref(&dir.path));
+
+    let mut config = Config::from("fallback = 'F'\n[matching]\n'firefox' = 'A'");
+    config.icon_set = IconSet::Ascii;
+    config.desktop_entries = desktop_entries;
+    let window = |app_id| Window {
+        app_id: Some(app_id),
+        ..Window::default()
+    };
+    // Without any stage the fallback is used
+    assert_eq!(config.fetch_fallback(&window("firefox-esr")).1, "F");
+    config.fallback_chain.desktop_entry = true;
+    config.fallback_chain.category = true;
+    // The rule for the icon name of the entry
+    let (index, icon) = config.fetch_fallback(&window("firefox-esr"));
+    assert_eq!(config.matchings[index].pattern_key(), "firefox");
+    assert_eq!(icon, "A");
+    // The icon for the category of the entry
+    let browser = category_icon(IconSet::Ascii, &["WebBrowser".to_string()]).unwrap();
+    assert_eq!(config.fetch_fallback(&window("Browser")).1, browser);
+    assert_eq!(config.fetch_fallback(&window("unknown")).1, "F");
+
+    config.fallback_chain.category = false;
+    assert_eq!(config.fetch_fallback(&window("Browser")).1, "F");
+}
+
+#[test]
+fn test_load_includes() {
+    let dir = crate::util::TestDir::new("load-includes");
+    dir.write(
+        "config.toml",
+        "include = ['team.toml']\nfallback = 'a'\n[matching]\n'app' = 'user'",
+    );
+    dir.write(
+        "team.toml",
+        "fallback = 'b'\n[matching]\n'app' = 'team'\n'team' = 'team'",
+    );
+
+    let config = Config::load(dir.join("config.toml")).unwrap();
+    assert_eq!(config.fallback(&Window::default()), "a");
+    assert_eq!(config.fetch_icon("app", None), "user");
+    assert_eq!(config.fetch_icon("team", None), "team");
+    assert_eq!(config.files.len(), 2);
+    assert_eq!(config.sources[0], Source::File(dir.join("config.toml")));
+    assert_eq!(config.sources[1], Source::File(dir.join("team.toml")));
+    assert_eq!(config.sources[3], Source::Default);
+}
+
+#[test]
+fn test_load_layers() {
+    let dir = crate::util::TestDir::new("load-layers");
+    dir.write(
+        "user/config.toml",
+        "ignore_smaller_than = [10, 20]\n[matching]\n'app' = 'user'",
+    );
+    dir.write(
+        "system/config.toml",
+        "fallback = 'system'\nignore_smaller_than = [30, 40]\n[matching]\n'app' = 'system'\n'other' = 'system'",
+    );
+
+    let config = Config::load_layers(&[
+        dir.join("user/config.toml"),
+        dir.join("missing/config.toml"),
+        dir.join("system/config.toml"),
+    ])
+    .unwrap();
+    assert_eq!(config.fallback(&Window::default()), "system");
+    assert_eq!(config.fetch_icon("app", None), "user");
+    assert_eq!(config.fetch_icon("other", None), "system");
+    assert_eq!(config.ignore_smaller_than, Some((10, 20)));
+}
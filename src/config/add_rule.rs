@@ -0,0 +1,108 @@
+use std::{fs, path::Path};
+
+use toml_edit::{value, DocumentMut, InlineTable, Item, Table};
+
+use super::{config_error::ConfigError, parse_content_to_config::parse_content_to_config};
+
+/// How a rule added from the command line matches windows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleType {
+    /// Match the app id or class
+    Exact,
+    /// Match the title
+    Generic,
+}
+
+/// Add a rule to the matching table of a config file, keeping its comments and order.
+/// An existing rule for the pattern gets the new value in its place. The file is created
+/// when it does not exist. Without a rule type `/regex/` patterns are generic and other
+/// patterns exact, like in the config itself.
+pub fn add_rule(
+    path: &Path,
+    pattern: &str,
+    icon: &str,
+    rule_type: Option<RuleType>,
+) -> Result<(), ConfigError> {
+    let content = if path.exists() {
+        fs::read_to_string(path)?
+    } else {
+        String::new()
+    };
+    let mut doc = content
+        .parse::<DocumentMut>()
+        .map_err(|e| ConfigError::new(format!("Could not parse {}: {e}", path.display())))?;
+
+    let matching = doc
+        .entry("matching")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_like_mut()
+        .ok_or(ConfigError::new("Could not parse matching table"))?;
+
+    let is_regex = pattern.starts_with('/') && pattern.ends_with('/');
+    let item = match (rule_type, is_regex) {
+        (Some(RuleType::Generic), false) | (Some(RuleType::Exact), true) => {
+            let mut table = InlineTable::new();
+            let match_type = match rule_type {
+                Some(RuleType::Exact) => "exact",
+                _ => "generic",
+            };
+            table.insert("type", match_type.into());
+            table.insert("value", icon.into());
+            value(table)
+        }
+        _ => value(icon),
+    };
+    // An existing rule keeps its place and comments
+    match matching.get_mut(pattern) {
+        Some(existing) => *existing = item,
+        None => {
+            matching.insert(pattern, item);
+        }
+    }
+
+    let content = doc.to_string();
+    // Don't write a config that can't be loaded anymore
+    parse_content_to_config(&content)?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[test]
+fn test_add_rule() {
+    use super::{Match, Pattern};
+
+    // The file is created in a directory that doesn't exist yet
+    let dir = crate::util::TestDir::new("add-rule");
+    let path = dir.join("config/config.toml");
+
+    add_rule(&path, "firefox", "A", None).unwrap();
+    fs::write(
+        &path,
+        "# My icons\nfallback = 'F'\n\n[matching]\n# browsers\n'firefox' = 'A'\n",
+    )
+    .unwrap();
+
+    add_rule(&path, "/term/", "B", None).unwrap();
+    add_rule(&path, "Title", "C", Some(RuleType::Generic)).unwrap();
+    add_rule(&path, "firefox", "D", None).unwrap();
+    assert!(add_rule(&path, "/[/", "E", None).is_err());
+
+    let content = fs::read_to_string(&path).unwrap();
+    assert!(content.starts_with("# My icons\nfallback = 'F'\n\n[matching]\n# browsers\n"));
+
+    let config = parse_content_to_config(&content).unwrap();
+    let keys: Vec<String> = config.matchings.iter().map(|m| m.pattern_key()).collect();
+    assert_eq!(keys, ["firefox", "/term/", "Title"]);
+    assert_eq!(
+        config.matchings[2],
+        Match::Generic {
+            pattern: Pattern::String("Title".to_string()),
+            value: "C".to_string()
+        }
+    );
+    assert_eq!(config.matchings[0].value(), "D");
+}
@@ -0,0 +1,226 @@
+use std::{
+    fs::read_to_string,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use toml::{
+    de::{DeTable, DeValue},
+    Table, Value,
+};
+
+use super::{
+    config_error::{ConfigError, Location},
+    parse_content_to_config::{parse_size, parse_table_to_config, KEYS},
+    read_config_files::{conf_d_dir, resolve_include, LIST_KEYS},
+};
+
+/// Keys of which every entry is checked on its own
+const SPLIT_TABLES: &[&str] = &["matching", "workspaces"];
+
+/// Check config layers with their includes and drop-in files.
+/// Unlike loading a config this does not stop at the first problem and
+/// reports the location of every problem.
+pub fn check_config_files<P: AsRef<Path>>(config_paths: &[P]) -> Vec<ConfigError> {
+    let mut errors = vec![];
+    let mut files = vec![];
+    let mut has_matching = false;
+
+    for path in config_paths.iter().map(|p| p.as_ref()) {
+        if config_paths.len() > 1 && !path.exists() {
+            continue;
+        }
+        has_matching |= check_file(path, &mut files, &mut vec![], &mut errors);
+
+        if let Some(Ok(entries)) = conf_d_dir(path).map(std::fs::read_dir) {
+            let mut drop_ins: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+                .collect();
+            drop_ins.sort();
+            for drop_in in drop_ins {
+                has_matching |= check_file(&drop_in, &mut files, &mut vec![], &mut errors);
+            }
+        }
+    }
+
+    if errors.is_empty() && !has_matching {
+        errors.push(ConfigError::new("Matching table not found"));
+    }
+
+    errors
+}
+
+/// Check a single file and its includes, returns if a matching table has been found.
+/// `stack` are the files including it.
+fn check_file(
+    path: &Path,
+    files: &mut Vec<PathBuf>,
+    stack: &mut Vec<PathBuf>,
+    errors: &mut Vec<ConfigError>,
+) -> bool {
+    if stack.iter().any(|f| f == path) {
+        errors.push(ConfigError::new(format!("{path:?} includes itself")));
+        return false;
+    }
+    // Already checked when included through another file
+    if files.iter().any(|f| f == path) {
+        return false;
+    }
+    files.push(path.to_path_buf());
+
+    let content = match read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            errors.push(ConfigError::new(format!("Could not read {path:?}: {e}")));
+            return false;
+        }
+    };
+    let error_at = |message: String, span: Range<usize>| {
+        ConfigError::new(message).with_location(Location::from_offset(
+            path.to_path_buf(),
+            &content,
+            span.start,
+        ))
+    };
+
+    let (document, syntax_errors) = DeTable::parse_recoverable(&content);
+    if !syntax_errors.is_empty() {
+        for e in syntax_errors {
+            errors.push(error_at(e.message().to_string(), e.span().unwrap_or(0..0)));
+        }
+        return false;
+    }
+    let root: Table = match toml::from_str(&content) {
+        Ok(root) => root,
+        Err(e) => {
+            errors.push(error_at(e.message().to_string(), e.span().unwrap_or(0..0)));
+            return false;
+        }
+    };
+
+    let mut has_matching = root.contains_key("matching");
+    for (key, de_value) in document.get_ref().iter() {
+        let name: &str = key.get_ref();
+        if !KEYS.contains(&name) {
+            errors.push(error_at(format!("Unknown key: {name}"), key.span()));
+            continue;
+        }
+        let value = &root[name];
+
+        match (de_value.get_ref(), value) {
+            (DeValue::Array(de_includes), Value::Array(includes)) if name == "include" => {
+                for (de_include, include) in de_includes.iter().zip(includes) {
+                    match include.as_str() {
+                        Some(include) => {
+                            let include = resolve_include(path, include);
+                            if !include.exists() {
+                                errors.push(error_at(
+                                    format!("Included file {include:?} does not exist"),
+                                    de_include.span(),
+                                ));
+                            } else {
+                                stack.push(path.to_path_buf());
+                                has_matching |= check_file(&include, files, stack, errors);
+                                stack.pop();
+                            }
+                        }
+                        None => errors.push(error_at(
+                            format!("Include {include} is not a path"),
+                            de_include.span(),
+                        )),
+                    }
+                }
+            }
+            (DeValue::Table(de_table), Value::Table(table)) if SPLIT_TABLES.contains(&name) => {
+                for (de_key, _) in de_table.iter() {
+                    let sub_key: &str = de_key.get_ref();
+                    let mut part = Table::new();
+                    part.insert(sub_key.to_string(), table[sub_key].clone());
+                    if let Some(e) = check_part(name, Value::Table(part)) {
+                        errors.push(error_at(e.message().to_string(), de_key.span()));
+                    }
+                }
+            }
+            // Point at the width or height that is wrong
+            (DeValue::Array(de_array), Value::Array(array))
+                if name == "ignore_smaller_than" && array.len() == 2 =>
+            {
+                for (de_element, element) in de_array.iter().zip(array) {
+                    if let Err(e) = parse_size(element) {
+                        errors.push(error_at(e.message().to_string(), de_element.span()));
+                    }
+                }
+            }
+            (DeValue::Array(de_array), Value::Array(array)) if LIST_KEYS.contains(&name) => {
+                for (de_element, element) in de_array.iter().zip(array) {
+                    if let Some(e) = check_part(name, Value::Array(vec![element.clone()])) {
+                        errors.push(error_at(e.message().to_string(), de_element.span()));
+                    }
+                }
+            }
+            _ => {
+                if let Some(e) = check_part(name, value.clone()) {
+                    errors.push(error_at(e.message().to_string(), key.span()));
+                }
+            }
+        }
+    }
+
+    has_matching
+}
+
+/// Parse a single key of a config on its own
+fn check_part(key: &str, value: Value) -> Option<ConfigError> {
+    let mut table = Table::new();
+    table.insert(key.to_string(), value);
+    table
+        .entry("matching")
+        .or_insert(Value::Table(Table::new()));
+    parse_table_to_config(&table).err()
+}
+
+#[test]
+fn test_check_config_files() {
+    let dir = crate::util::TestDir::new("check-config");
+    let path = dir.write(
+        "config.toml",
+        "fallback = 1\nunknown = 'a'\nignore_smaller_than = [10, 2.5]\n[matching]\n'a' = 'b'\n'/(/' = 'c'\n",
+    );
+
+    let errors: Vec<String> = check_config_files(&[&path])
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    assert_eq!(errors.len(), 4, "{errors:?}");
+    assert!(errors[0].ends_with("config.toml:1:1: Fallback is not a string"));
+    assert!(errors[1].ends_with("config.toml:2:1: Unknown key: unknown"));
+    assert!(
+        errors[2]
+            .ends_with("config.toml:3:28: ignore_smaller_than size 2.5 is not a positive integer"),
+        "{errors:?}"
+    );
+    assert!(errors[3].contains("config.toml:6:1: Invalid pattern given: /(/: regex parse error"));
+
+    dir.write("config.toml", "[matching]\na = \n");
+    let errors = check_config_files(&[&path]);
+    assert_eq!(errors[0].location().unwrap().line, 2);
+
+    // Including the same file twice is fine, only cycles are a problem
+    dir.write(
+        "config.toml",
+        "include = ['a.toml', 'a.toml', 'b.toml']\n[matching]",
+    );
+    dir.write("a.toml", "");
+    dir.write("b.toml", "include = ['config.toml']");
+    let errors: Vec<String> = check_config_files(&[&path])
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(
+        errors[0].contains("config.toml\" includes itself"),
+        "{errors:?}"
+    );
+}
@@ -1,21 +1,67 @@
-use std::{error::Error, fmt::Display};
+use std::{error::Error, fmt::Display, path::PathBuf};
 
 #[derive(Debug)]
 pub struct ConfigError {
     message: String,
+    location: Option<Location>,
+}
+
+/// Position of an error in a config file, line and column start at 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// Get the location of a byte offset in `content`
+    pub fn from_offset(file: PathBuf, content: &str, offset: usize) -> Location {
+        let before = &content[..offset.min(content.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = match before.rfind('\n') {
+            Some(i) => before[i + 1..].chars().count() + 1,
+            None => before.chars().count() + 1,
+        };
+        Location { file, line, column }
+    }
 }
 
 impl ConfigError {
     pub fn new<S: Into<String>>(message: S) -> ConfigError {
         ConfigError {
             message: message.into(),
+            location: None,
         }
     }
+
+    pub fn with_location(mut self, location: Location) -> ConfigError {
+        self.location = Some(location);
+        self
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
 }
 
-impl<'n> Display for ConfigError {
+impl Display for ConfigError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return write!(f, "{}", self.message);
+        match &self.location {
+            Some(l) => write!(
+                f,
+                "{}:{}:{}: {}",
+                l.file.display(),
+                l.line,
+                l.column,
+                self.message
+            ),
+            None => write!(f, "{}", self.message),
+        }
     }
 }
 
@@ -25,4 +71,10 @@ impl From<toml::de::Error> for ConfigError {
     }
 }
 
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::new(e.to_string())
+    }
+}
+
 impl Error for ConfigError {}
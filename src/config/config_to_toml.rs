@@ -0,0 +1,319 @@
+use std::collections::HashSet;
+
+use toml::{Table, Value};
+
+use super::{
+    Config, DisplayMode, Match, Pattern, RenamePolicy, RuleStyle, Source, WorkspaceSelector,
+};
+
+/// Serialize the effective config to toml. The default rules are written out as part of the
+/// matching table, so `use_defaults` is always disabled.
+pub fn config_to_toml(config: &Config) -> String {
+    let mut root = Table::new();
+
+    if let Some(fallback) = &config.fallback {
+        root.insert("fallback".to_string(), Value::from(fallback.as_str()));
+    }
+    if let Some(separator) = &config.separator {
+        root.insert("separator".to_string(), Value::from(separator.as_str()));
+    }
+    if let Some(max_icons) = config.max_icons {
+        root.insert("max_icons".to_string(), Value::from(max_icons as i64));
+    }
+    if let Some(max_width) = config.max_width {
+        root.insert("max_width".to_string(), Value::from(max_width as i64));
+    }
+    root.insert(
+        "ignore".to_string(),
+        Value::Array(config.ignore.iter().map(pattern_to_value).collect()),
+    );
+    root.insert(
+        "ignore_floating".to_string(),
+        Value::from(config.ignore_floating),
+    );
+    if let Some((width, height)) = config.ignore_smaller_than {
+        root.insert(
+            "ignore_smaller_than".to_string(),
+            Value::Array(vec![Value::from(width), Value::from(height)]),
+        );
+    }
+    root.insert(
+        "exclude_workspaces".to_string(),
+        Value::Array(
+            config
+                .exclude_workspaces
+                .iter()
+                .map(|w| match w {
+                    WorkspaceSelector::Num(num) => Value::from(*num),
+                    WorkspaceSelector::Name(name) => Value::from(name.as_str()),
+                })
+                .collect(),
+        ),
+    );
+    root.insert(
+        "exclude_outputs".to_string(),
+        Value::Array(
+            config
+                .exclude_outputs
+                .iter()
+                .map(|o| Value::from(o.as_str()))
+                .collect(),
+        ),
+    );
+    let on_foreign_rename = match config.on_foreign_rename {
+        RenamePolicy::Overwrite => "overwrite",
+        RenamePolicy::Respect => "respect",
+        RenamePolicy::BackOff => "back_off",
+    };
+    root.insert(
+        "on_foreign_rename".to_string(),
+        Value::from(on_foreign_rename),
+    );
+    root.insert("icon_set".to_string(), Value::from(config.icon_set.name()));
+    let display = match config.display {
+        DisplayMode::Icon => "icon",
+        DisplayMode::Label => "label",
+        DisplayMode::Both => "both",
+    };
+    root.insert("display".to_string(), Value::from(display));
+    root.insert("markup".to_string(), Value::from(config.markup));
+    root.insert("use_defaults".to_string(), Value::from(false));
+
+    let chain = config.fallback_chain;
+    let mut fallback_chain = Table::new();
+    for (stage, enabled) in [
+        ("desktop_entry", chain.desktop_entry),
+        ("category", chain.category),
+        ("first_letter", chain.first_letter),
+    ] {
+        fallback_chain.insert(stage.to_string(), Value::from(enabled));
+    }
+    root.insert("fallback_chain".to_string(), Value::Table(fallback_chain));
+
+    let mut workspaces = Table::new();
+    for w in &config.workspaces {
+        let key = w.num.to_string();
+        let value = if w.show_empty {
+            let mut table = Table::new();
+            table.insert("value".to_string(), Value::from(w.value.as_str()));
+            table.insert("show_empty".to_string(), Value::from(true));
+            Value::Table(table)
+        } else {
+            Value::from(w.value.as_str())
+        };
+        workspaces.insert(key, value);
+    }
+    root.insert("workspaces".to_string(), Value::Table(workspaces));
+
+    let combinations = config
+        .combinations
+        .iter()
+        .map(|c| {
+            let mut table = Table::new();
+            table.insert(
+                "apps".to_string(),
+                Value::Array(c.apps.iter().map(pattern_to_value).collect()),
+            );
+            table.insert(
+                "icons".to_string(),
+                Value::Array(c.icons.iter().map(|i| Value::from(i.as_str())).collect()),
+            );
+            table.insert("exclusive".to_string(), Value::from(c.exclusive));
+            table.insert("value".to_string(), Value::from(c.value.as_str()));
+            Value::Table(table)
+        })
+        .collect();
+    root.insert("combine".to_string(), Value::Array(combinations));
+
+    let mut content = toml::to_string(&root).expect("Could not serialize config");
+    content.push_str("\n[matching]\n");
+    content.push_str(&matchings_to_toml(config));
+    content
+}
+
+/// Write the matching table by hand, as the serializer would move verbose rules
+/// into their own tables after the simple ones and change the order of the rules
+fn matchings_to_toml(config: &Config) -> String {
+    let mut content = String::new();
+    // Rules are told apart by their kind and pattern, a later rule with the same kind and
+    // pattern can never match
+    let mut rules = HashSet::new();
+    let mut keys = HashSet::new();
+    let mut source = None;
+
+    for (i, m) in config.matchings.iter().enumerate() {
+        let rule_source = config.sources.get(i).unwrap_or(&Source::Content);
+        if source != Some(rule_source) {
+            content.push_str(&format!("# {rule_source}\n"));
+            source = Some(rule_source);
+        }
+
+        let key = m.pattern_key();
+        let kind = match m {
+            Match::Generic { .. } => "generic",
+            Match::Exact { .. } => "exact",
+        };
+        if !rules.insert((kind, key.clone())) {
+            content.push_str(&format!(
+                "# {kind} '{key}' left out, duplicates an earlier rule\n"
+            ));
+            continue;
+        }
+
+        // Rules of another kind with the same pattern need a key of their own
+        let mut table_key = key.clone();
+        let mut n = 1;
+        while !keys.insert(table_key.clone()) {
+            table_key = match n {
+                1 => format!("{key} ({kind})"),
+                n => format!("{key} ({kind} {n})"),
+            };
+            n += 1;
+        }
+        let renamed = table_key != key;
+
+        // Types that differ from the standard format
+        let match_type = match m {
+            Match::Generic {
+                pattern: Pattern::String(_),
+                ..
+            } => Some("generic"),
+            Match::Exact { pattern, .. } if looks_like_regex(pattern) => Some("exact"),
+            _ if renamed => Some(kind),
+            _ => None,
+        };
+        let style = config.styles.get(i).cloned().unwrap_or_default();
+        let value = if match_type.is_none() && style == RuleStyle::default() {
+            Value::from(m.value().as_str())
+        } else {
+            let mut table = Table::new();
+            if renamed {
+                table.insert("pattern".to_string(), Value::from(key.as_str()));
+            }
+            if let Some(match_type) = match_type {
+                table.insert("type".to_string(), Value::from(match_type));
+            }
+            table.insert("value".to_string(), Value::from(m.value().as_str()));
+            for (key, value) in [
+                ("label", style.label),
+                ("color", style.color),
+                ("font", style.font),
+                ("size", style.size),
+            ] {
+                if let Some(value) = value {
+                    table.insert(key.to_string(), Value::from(value));
+                }
+            }
+            Value::Table(table)
+        };
+        content.push_str(&format!("{} = {value}\n", toml_key(&table_key)));
+    }
+
+    content
+}
+
+/// Serialize a match used as pattern list entry, of which the value is unused
+fn pattern_to_value(m: &Match) -> Value {
+    let key = m.pattern_key();
+    let match_type = match m {
+        Match::Generic {
+            pattern: Pattern::Regex(_),
+            ..
+        } => return Value::from(key),
+        Match::Generic { .. } => "generic",
+        Match::Exact { pattern, .. } if !looks_like_regex(pattern) => return Value::from(key),
+        Match::Exact { .. } => "exact",
+    };
+
+    let mut table = Table::new();
+    table.insert("pattern".to_string(), Value::from(key));
+    table.insert("type".to_string(), Value::from(match_type));
+    Value::Table(table)
+}
+
+/// Exact patterns written like a regex have to use the verbose format
+fn looks_like_regex(pattern: &str) -> bool {
+    pattern.starts_with('/') && pattern.ends_with('/')
+}
+
+/// Quote a key when needed
+fn toml_key(key: &str) -> String {
+    let mut table = Table::new();
+    table.insert(key.to_string(), Value::from(""));
+    let line = toml::to_string(&table).expect("Could not serialize key");
+    line.trim_end()
+        .strip_suffix(" = \"\"")
+        .unwrap_or(&line)
+        .to_string()
+}
+
+#[test]
+fn test_config_to_toml() {
+    let config = Config::from(
+        "
+    fallback = 'F'
+    max_icons = 3
+    ignore = ['pinentry', '/Picture-in-Picture/', { pattern = 'popup', type = 'generic' }]
+    combine = [{ apps = ['code'], icons = ['B'], value = 'dev' }]
+    exclude_workspaces = [10, 'scratch']
+    on_foreign_rename = 'respect'
+
+    [fallback_chain]
+    desktop_entry = true
+    category = false
+    first_letter = true
+
+    [workspaces]
+    1 = 'web'
+    9 = { value = 'C', show_empty = true }
+
+    [matching]
+    'firefox' = 'A'
+    '/^term\\s/' = 'B'
+    'title with \"quotes\"' = { type = 'generic', value = 'C' }
+    '/not a regex/' = { type = 'exact', value = 'D' }
+    'foot' = { value = 'E', label = 'term', color = '#ff0000', size = 12 }
+    ",
+    );
+
+    let content = config_to_toml(&config);
+    let parsed = Config::parse(&content).unwrap_or_else(|e| panic!("{e}\n{content}"));
+
+    assert_eq!(parsed.matchings[..5], config.matchings[..5]);
+    assert_eq!(parsed.styles[..5], config.styles[..5]);
+    assert_eq!(parsed.matchings.len(), config.matchings.len() - 2);
+    assert_eq!(parsed.fallback, config.fallback);
+    assert_eq!(parsed.max_icons, Some(3));
+    assert_eq!(parsed.ignore, config.ignore);
+    assert_eq!(parsed.combinations, config.combinations);
+    assert_eq!(parsed.workspaces, config.workspaces);
+    assert_eq!(parsed.exclude_workspaces, config.exclude_workspaces);
+    assert_eq!(parsed.on_foreign_rename, RenamePolicy::Respect);
+    assert_eq!(parsed.fallback_chain, config.fallback_chain);
+    assert!(parsed.fallback_chain.desktop_entry);
+    assert!(!parsed.fallback_chain.category);
+    assert!(!parsed.use_defaults);
+}
+
+#[test]
+fn test_config_to_toml_round_trip() {
+    // Each of these has the pattern of a rule of another kind
+    let config = Config::from(
+        "
+    [matching]
+    'firefox' = { type = 'generic', value = 'A' }
+    '/not a regex/' = { type = 'exact', value = 'B' }
+    'steam' = { type = 'generic', value = 'C' }
+    '/steam/' = 'D'
+    ",
+    );
+
+    let content = config_to_toml(&config);
+    let parsed = Config::parse(&content).unwrap_or_else(|e| panic!("{e}\n{content}"));
+    let printed = Config::parse(&config_to_toml(&parsed)).unwrap();
+
+    // None of the rules duplicates an earlier rule of the same kind, so none are left out
+    assert_eq!(parsed.matchings, config.matchings);
+    assert_eq!(printed.matchings, config.matchings);
+    assert!(content.contains(r#""firefox (exact)" = { pattern = "firefox", type = "exact""#));
+}
@@ -0,0 +1,169 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+/// The parts of a `.desktop` entry used to find an icon for a window
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DesktopEntry {
+    pub name: Option<String>,
+    /// Name of the icon in the icon theme, often the name of the app
+    pub icon: Option<String>,
+    pub startup_wm_class: Option<String>,
+    pub categories: Vec<String>,
+}
+
+impl DesktopEntry {
+    /// Parse the `[Desktop Entry]` group, localized keys are left out
+    pub fn parse(content: &str) -> DesktopEntry {
+        let mut entry = DesktopEntry::default();
+        let mut in_group = false;
+
+        for line in content.lines().map(|l| l.trim()) {
+            if line.starts_with('[') {
+                in_group = line == "[Desktop Entry]";
+                continue;
+            }
+            if !in_group || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim() {
+                "Name" => entry.name = Some(value),
+                "Icon" => entry.icon = Some(value),
+                "StartupWMClass" => entry.startup_wm_class = Some(value),
+                "Categories" => {
+                    entry.categories = value
+                        .split(';')
+                        .filter(|c| !c.is_empty())
+                        .map(String::from)
+                        .collect()
+                }
+                _ => {}
+            }
+        }
+
+        entry
+    }
+
+    /// Names the app might be known by in the rules
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        [&self.icon, &self.startup_wm_class, &self.name]
+            .into_iter()
+            .flatten()
+    }
+}
+
+/// The desktop entries of the installed apps by file name, read at once as reading them
+/// for every window would be slow
+#[derive(Clone, Debug, Default)]
+pub struct DesktopEntries {
+    entries: Vec<(String, DesktopEntry)>,
+}
+
+impl DesktopEntries {
+    /// Read the entries in the xdg data dirs
+    pub fn load() -> DesktopEntries {
+        let entries = DesktopEntries::load_from(&application_dirs());
+        debug!("Read {} desktop entries", entries.entries.len());
+        entries
+    }
+
+    /// Read the entries in `dirs`, entries in earlier dirs hide those with the same name
+    /// in later dirs
+    pub(super) fn load_from(dirs: &[PathBuf]) -> DesktopEntries {
+        let mut entries: Vec<(String, DesktopEntry)> = vec![];
+        for file in dirs.iter().flat_map(|dir| desktop_files(dir)) {
+            let Some(stem) = file.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+                continue;
+            };
+            if entries.iter().any(|(s, _)| *s == stem) {
+                continue;
+            }
+            if let Ok(content) = fs::read_to_string(&file) {
+                entries.push((stem, DesktopEntry::parse(&content)));
+            }
+        }
+        DesktopEntries { entries }
+    }
+
+    /// Find the entry of an app by its app id or class. Looks for an entry named after the
+    /// app, then for one of which the last part of the name (like `Nautilus` of
+    /// `org.gnome.Nautilus`) or the window class is the app.
+    pub fn find(&self, app: &str) -> Option<&DesktopEntry> {
+        let is_app = |name: &str| name.eq_ignore_ascii_case(app);
+        self.entries
+            .iter()
+            .find(|(stem, _)| is_app(stem))
+            .or_else(|| {
+                self.entries.iter().find(|(stem, entry)| {
+                    stem.rsplit('.').next().is_some_and(is_app)
+                        || entry.startup_wm_class.as_deref().is_some_and(is_app)
+                })
+            })
+            .map(|(_, entry)| entry)
+    }
+}
+
+/// The `applications` directories of the xdg data dirs, most important first
+fn application_dirs() -> Vec<PathBuf> {
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+
+    dirs::data_dir()
+        .into_iter()
+        .chain(env::split_paths(&data_dirs))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+fn desktop_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "desktop"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_find_desktop_entry() {
+    let dir = crate::util::TestDir::new("desktop-entry");
+    dir.write(
+        "system/org.gnome.Nautilus.desktop",
+        "[Desktop Entry]\nName=Files\nName[de]=Dateien\nIcon=org.gnome.Nautilus\nCategories=GNOME;Utility;FileManager;\n\n[Desktop Action new-window]\nName=New Window\n",
+    );
+    dir.write(
+        "system/code.desktop",
+        "[Desktop Entry]\nName=Visual Studio Code\nIcon=vscode\nStartupWMClass=Code\n",
+    );
+    dir.write(
+        "local/code.desktop",
+        "[Desktop Entry]\nName=Code\nIcon=code-local\n",
+    );
+    let entries = DesktopEntries::load_from(&[dir.join("local"), dir.join("system")]);
+
+    let entry = entries.find("org.gnome.nautilus").unwrap();
+    assert_eq!(entry.name.as_deref(), Some("Files"));
+    assert_eq!(entry.categories, vec!["GNOME", "Utility", "FileManager"]);
+    assert_eq!(entries.find("nautilus"), Some(entry));
+    assert_eq!(
+        entries
+            .find("Code")
+            .unwrap()
+            .names()
+            .collect::<Vec<&String>>(),
+        vec!["code-local", "Code"]
+    );
+    assert_eq!(entries.find("unknown"), None);
+}
@@ -0,0 +1,66 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use toml::Table;
+
+use super::config_error::ConfigError;
+
+const ICON_NAMES: &str = include_str!("../../icon_names.toml");
+
+/// Glyphs per icon set prefix and icon name
+static ICONS: LazyLock<HashMap<String, HashMap<String, char>>> = LazyLock::new(|| {
+    let table: Table = toml::from_str(ICON_NAMES).expect("Invalid icon names");
+    table
+        .iter()
+        .map(|(prefix, names)| {
+            let names = names
+                .as_table()
+                .expect("Icon set is not a table")
+                .iter()
+                .map(|(name, codepoint)| {
+                    let glyph = codepoint
+                        .as_str()
+                        .and_then(|c| u32::from_str_radix(c, 16).ok())
+                        .and_then(char::from_u32)
+                        .expect("Invalid icon codepoint");
+                    (name.clone(), glyph)
+                })
+                .collect();
+            (prefix.clone(), names)
+        })
+        .collect()
+});
+
+/// Resolve a value like `fa:firefox` to its glyph, other values are returned as is
+pub fn resolve_icon_name(value: &str) -> Result<String, ConfigError> {
+    let Some((prefix, name)) = value.split_once(':') else {
+        return Ok(value.to_string());
+    };
+    let Some(names) = ICONS.get(prefix) else {
+        return Ok(value.to_string());
+    };
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Ok(value.to_string());
+    }
+
+    names
+        .get(name)
+        .map(|glyph| glyph.to_string())
+        .ok_or(ConfigError::new(format!("Unknown icon name: {value}")))
+}
+
+#[test]
+fn test_resolve_icon_name() {
+    assert_eq!(resolve_icon_name("fa:firefox").unwrap(), "\u{f269}");
+    assert_eq!(resolve_icon_name("nf:dev-rust").unwrap(), "\u{e7a8}");
+    assert_eq!(resolve_icon_name("A").unwrap(), "A");
+    assert_eq!(resolve_icon_name("fa: web").unwrap(), "fa: web");
+    assert_eq!(resolve_icon_name("dev:code").unwrap(), "dev:code");
+    assert_eq!(
+        resolve_icon_name("fa:not-an-icon").unwrap_err().to_string(),
+        "Unknown icon name: fa:not-an-icon"
+    );
+}
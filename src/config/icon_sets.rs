@@ -0,0 +1,144 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use toml::Table;
+
+use super::{icon_names::resolve_icon_name, Config, IconSet, RuleStyle};
+
+const ICON_SETS: &str = include_str!("../../icon_sets.toml");
+const CATEGORY_ICONS: &str = include_str!("../../category_icons.toml");
+
+/// Fallback and values per default rule pattern, for each icon set except the default one
+struct DefaultValues {
+    fallback: String,
+    matching: HashMap<String, String>,
+}
+
+static SETS: LazyLock<HashMap<String, DefaultValues>> = LazyLock::new(|| {
+    let table: Table = toml::from_str(ICON_SETS).expect("Invalid icon sets");
+    let value = |v: &toml::Value| {
+        resolve_icon_name(v.as_str().expect("Icon set value is not a string"))
+            .expect("Invalid icon set value")
+    };
+    table
+        .iter()
+        .map(|(name, set)| {
+            let fallback = value(set.get("fallback").expect("Icon set without fallback"));
+            let matching = set
+                .get("matching")
+                .and_then(|m| m.as_table())
+                .expect("Icon set without matching table")
+                .iter()
+                .map(|(pattern, v)| (pattern.clone(), value(v)))
+                .collect();
+            (name.clone(), DefaultValues { fallback, matching })
+        })
+        .collect()
+});
+
+/// Icons per desktop entry category for each icon set, in order of preference
+static CATEGORIES: LazyLock<HashMap<String, Vec<(String, String)>>> = LazyLock::new(|| {
+    let table: Table = toml::from_str(CATEGORY_ICONS).expect("Invalid category icons");
+    table
+        .iter()
+        .map(|(name, categories)| {
+            let categories = categories
+                .as_table()
+                .expect("Category icons are not a table")
+                .iter()
+                .map(|(category, v)| {
+                    let icon = v
+                        .as_str()
+                        .map(resolve_icon_name)
+                        .expect("Category icon is not a string")
+                        .expect("Invalid category icon");
+                    (category.clone(), icon)
+                })
+                .collect();
+            (name.clone(), categories)
+        })
+        .collect()
+});
+
+/// Icon of the most specific of the given desktop entry categories
+pub fn category_icon(icon_set: IconSet, categories: &[String]) -> Option<String> {
+    CATEGORIES
+        .get(icon_set.name())?
+        .iter()
+        .find(|(category, _)| categories.contains(category))
+        .map(|(_, icon)| icon.clone())
+}
+
+/// Replace the values of the default config with those of an icon set
+pub fn apply_icon_set(default: &mut Config, icon_set: IconSet) {
+    let Some(set) = SETS.get(icon_set.name()) else {
+        return;
+    };
+
+    default.fallback = Some(set.fallback.clone());
+    for m in &mut default.matchings {
+        if let Some(value) = set.matching.get(&m.pattern_key()) {
+            *m.value_mut() = value.clone();
+        }
+    }
+}
+
+/// Use the text of the ascii icon set as labels of the default rules
+pub fn apply_default_labels(default: &mut Config) {
+    let Some(set) = SETS.get(IconSet::Ascii.name()) else {
+        return;
+    };
+
+    default
+        .styles
+        .resize(default.matchings.len(), RuleStyle::default());
+    for (m, style) in default.matchings.iter().zip(&mut default.styles) {
+        if let Some(label) = set.matching.get(&m.pattern_key()) {
+            style.label = Some(label.clone());
+        }
+    }
+}
+
+#[test]
+fn test_icon_sets_complete() {
+    for icon_set in [IconSet::NerdFont, IconSet::Emoji, IconSet::Ascii] {
+        let set = SETS.get(icon_set.name()).unwrap();
+        let default = Config::default();
+        for m in &default.matchings {
+            assert!(
+                set.matching.contains_key(&m.pattern_key()),
+                "{} has no value for '{}'",
+                icon_set.name(),
+                m.pattern_key()
+            );
+        }
+        assert_eq!(set.matching.len(), default.matchings.len());
+    }
+
+    let config = Config::from("icon_set = 'ascii'\n[matching]\n'foo' = 'bar'");
+    assert_eq!(config.fetch_icon("firefox", None), "web");
+    assert_eq!(config.fetch_icon("foo", None), "bar");
+    assert_eq!(config.fallback.as_deref(), Some("*"));
+}
+
+#[test]
+fn test_category_icon() {
+    let categories = vec!["Network".to_string(), "WebBrowser".to_string()];
+    assert_eq!(
+        category_icon(IconSet::FontAwesome, &categories).as_deref(),
+        Some("\u{f0ac}")
+    );
+    assert_eq!(
+        category_icon(IconSet::Ascii, &categories).as_deref(),
+        Some("web")
+    );
+    assert_eq!(
+        category_icon(IconSet::Emoji, &["Unknown".to_string()]),
+        None
+    );
+    for icon_set in [IconSet::NerdFont, IconSet::Emoji, IconSet::Ascii] {
+        assert_eq!(
+            CATEGORIES[icon_set.name()].len(),
+            CATEGORIES[IconSet::FontAwesome.name()].len()
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt::Display;
+
+use super::{Config, Match, Pattern, Source};
+
+/// How bad a problem found by `lint_config` is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// Usually intended, like overriding a default rule to change its icon
+    Info,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Find rules that can never match and rules that duplicate other rules.
+/// Problems between two default rules are not reported.
+pub fn lint_config(config: &Config) -> Vec<(Severity, String)> {
+    let mut warnings = vec![];
+    let source = |i: usize| config.sources.get(i).unwrap_or(&Source::Content);
+    let is_default = |i: usize| *source(i) == Source::Default;
+
+    for (j, later) in config.matchings.iter().enumerate() {
+        let key = later.pattern_key();
+
+        if !is_default(j) && matches_everything(later) {
+            warnings.push((
+                Severity::Warning,
+                format!(
+                    "{}: '{key}' matches every title, later rules can never match",
+                    source(j)
+                ),
+            ));
+        }
+
+        for (i, earlier) in config.matchings[..j].iter().enumerate() {
+            if is_default(i) && is_default(j) {
+                continue;
+            }
+            let earlier_key = earlier.pattern_key();
+
+            let same_kind = matches!(
+                (earlier, later),
+                (Match::Exact { .. }, Match::Exact { .. })
+                    | (Match::Generic { .. }, Match::Generic { .. })
+            );
+            if same_kind && earlier_key == key {
+                if is_default(j) && earlier.value() == later.value() {
+                    warnings.push((
+                        Severity::Warning,
+                        format!("{}: '{key}' is the same as the default rule", source(i)),
+                    ));
+                } else if is_default(j) {
+                    warnings.push((
+                        Severity::Info,
+                        format!("{}: '{key}' overrides the default rule", source(i)),
+                    ));
+                } else {
+                    warnings.push((
+                        Severity::Warning,
+                        format!(
+                            "{}: '{key}' is unreachable, it duplicates the rule in {}",
+                            source(j),
+                            source(i)
+                        ),
+                    ));
+                }
+                continue;
+            }
+
+            if let (Match::Exact { .. }, Match::Exact { .. }) = (earlier, later) {
+                if earlier_key.eq_ignore_ascii_case(&key) {
+                    warnings.push((
+                        Severity::Warning,
+                        format!(
+                            "{}: '{key}' only differs in case from '{earlier_key}' in {}",
+                            source(j),
+                            source(i)
+                        ),
+                    ));
+                }
+            }
+
+            if shadows(earlier, later) {
+                warnings.push((Severity::Warning, format!(
+                    "{}: '{key}' is unreachable, every title it matches is matched by the earlier rule '{earlier_key}' in {}",
+                    source(j),
+                    source(i)
+                )));
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Check if a generic rule matches any title
+fn matches_everything(m: &Match) -> bool {
+    match m {
+        Match::Generic {
+            pattern: Pattern::String(s),
+            ..
+        } => s.is_empty(),
+        Match::Generic {
+            pattern: Pattern::Regex(r),
+            ..
+        } => ["", "a", "Z 9", "\n", "title - app"]
+            .iter()
+            .all(|s| r.is_match(s)),
+        Match::Exact { .. } => false,
+    }
+}
+
+/// Check if every title matched by `later` is also matched by `earlier`
+fn shadows(earlier: &Match, later: &Match) -> bool {
+    let (earlier, later) = match (earlier, later) {
+        (Match::Generic { pattern: e, .. }, Match::Generic { pattern: l, .. }) => (e, l),
+        _ => return false,
+    };
+
+    match (earlier, later) {
+        (Pattern::String(e), Pattern::String(l)) => l.to_lowercase().contains(&e.to_lowercase()),
+        (Pattern::String(e), Pattern::Regex(l)) => {
+            is_literal(l.as_str()) && l.as_str().to_lowercase().contains(&e.to_lowercase())
+        }
+        (Pattern::Regex(e), Pattern::Regex(l)) => {
+            is_unanchored(e.as_str()) && is_literal(l.as_str()) && e.is_match(l.as_str())
+        }
+        // The string matches titles in any case, so only a regex ignoring case can cover it
+        (Pattern::Regex(e), Pattern::String(l)) => {
+            is_case_insensitive(e.as_str()) && is_unanchored(e.as_str()) && e.is_match(l)
+        }
+    }
+}
+
+/// A regex ignoring case as a whole, which doesn't turn it off again
+fn is_case_insensitive(regex: &str) -> bool {
+    regex.starts_with("(?i)") && !regex.contains("-i")
+}
+
+/// A regex without special characters only matches titles containing it
+fn is_literal(regex: &str) -> bool {
+    regex::escape(regex) == regex
+}
+
+/// An unanchored regex matching a string also matches every string containing it
+fn is_unanchored(regex: &str) -> bool {
+    !["^", "$", "\\b", "\\B", "\\A", "\\z", "\\<", "\\>"]
+        .iter()
+        .any(|anchor| regex.contains(anchor))
+}
+
+#[test]
+fn test_lint_config() {
+    let config = Config::from(
+        "
+    [matching]
+    '/node/' = 'a'
+    '/node server/' = 'b'
+    '/^vim/' = 'c'
+    '/vim editor/' = 'd'
+    '/.*/' = 'e'
+    'firefox' = 'f'
+    'Spotify' = 'g'
+    'SPOTIFY' = 'h'
+    '/(node|NODE|Node)/' = { type = 'generic', value = 'i' }
+    'node' = { type = 'generic', value = 'j' }
+    '/(?i)steam/' = 'k'
+    'steam' = { type = 'generic', value = 'l' }
+    ",
+    );
+
+    let warnings = lint_config(&config);
+    let has = |s: &str| warnings.iter().any(|(_, w)| w.contains(s));
+    assert!(has("'/node server/' is unreachable"), "{warnings:?}");
+    assert!(!has("'/vim editor/' is unreachable"), "{warnings:?}");
+    // "nOde" is only matched by the later rule
+    assert!(!has("'node' is unreachable"), "{warnings:?}");
+    assert!(has("'steam' is unreachable"), "{warnings:?}");
+    assert!(has("'/.*/' matches every title"), "{warnings:?}");
+    assert!(has("'firefox' overrides the default rule"), "{warnings:?}");
+    assert!(
+        has("'SPOTIFY' only differs in case from 'Spotify'"),
+        "{warnings:?}"
+    );
+    assert!(has("'Spotify' overrides the default rule"), "{warnings:?}");
+    assert!(warnings
+        .iter()
+        .any(|(severity, w)| *severity == Severity::Info && w.contains("'firefox' overrides")));
+}
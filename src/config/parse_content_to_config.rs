@@ -1,20 +1,54 @@
 use std::convert::TryFrom;
 
-use toml::Value;
+use toml::{Table, Value};
 
-use super::{config_error::ConfigError, Config, Match, Pattern};
+use super::{
+    config_error::ConfigError, icon_names::resolve_icon_name, Combination, Config, DisplayMode,
+    FallbackChain, IconSet, Match, Pattern, RenamePolicy, RuleStyle, WorkspaceSelector,
+    WorkspaceStyle,
+};
+
+/// All keys that can be used in the root table of a config
+pub const KEYS: &[&str] = &[
+    "include",
+    "matching",
+    "fallback",
+    "separator",
+    "max_icons",
+    "max_width",
+    "ignore",
+    "ignore_floating",
+    "ignore_smaller_than",
+    "combine",
+    "workspaces",
+    "exclude_workspaces",
+    "exclude_outputs",
+    "on_foreign_rename",
+    "icon_set",
+    "display",
+    "markup",
+    "fallback_chain",
+    "use_defaults",
+    "disable_defaults",
+];
 
 /// Parse toml config content to icon_map
-pub fn parse_content_to_config(content: &String) -> Result<Config, ConfigError> {
+pub fn parse_content_to_config(content: &str) -> Result<Config, ConfigError> {
     let map: Value = toml::from_str(content)?;
 
+    match map {
+        Value::Table(root) => parse_table_to_config(&root),
+        _ => Err(ConfigError::new("No root table found")),
+    }
+}
+
+/// Parse a toml root table to a config
+pub fn parse_table_to_config(root: &Table) -> Result<Config, ConfigError> {
     let map_to_match = |k: (&String, &Value)| -> Result<Match, ConfigError> {
         if let Some(value) = k.1.as_str() {
-            let value = value.to_string();
-            let pattern = Pattern::try_from(k.0.to_string()).or(Err(ConfigError::new(format!(
-                "Invalid pattern given: {}",
-                k.0
-            ))))?;
+            let value = resolve_icon_name(value)?;
+            let pattern = Pattern::try_from(k.0.to_string())
+                .map_err(|e| ConfigError::new(format!("Invalid pattern given: {}: {e}", k.0)))?;
 
             match pattern {
                 Pattern::Regex(_) => return Ok(Match::Generic { pattern, value }),
@@ -23,14 +57,22 @@ pub fn parse_content_to_config(content: &String) -> Result<Config, ConfigError>
         }
 
         if let Some(table) = k.1.as_table() {
-            let match_type = table
-                .get("type")
-                .ok_or(ConfigError::new(format!("Could not parse: {}", k.0)))?
-                .as_str()
-                .ok_or(ConfigError::new(format!(
-                    "Value of {} is not a string",
+            // Rules of different types with the same pattern can't share a key
+            let pattern = match table.get("pattern") {
+                Some(pattern) => pattern.as_str().ok_or(ConfigError::new(format!(
+                    "Pattern of {} is not a string",
                     k.0
-                )))?;
+                )))?,
+                None => k.0.as_str(),
+            };
+            let match_type = match table.get("type") {
+                Some(match_type) => match_type
+                    .as_str()
+                    .ok_or(ConfigError::new(format!("Type of {} is not a string", k.0)))?,
+                // Same as the standard format
+                None if pattern.starts_with('/') && pattern.ends_with('/') => "generic",
+                None => "exact",
+            };
 
             let value = table
                 .get("value")
@@ -39,18 +81,18 @@ pub fn parse_content_to_config(content: &String) -> Result<Config, ConfigError>
                 .ok_or(ConfigError::new(format!(
                     "Value of {} is not a string",
                     k.0
-                )))?
-                .to_string();
+                )))?;
+            let value = resolve_icon_name(value)?;
 
-            let m = match &match_type[..] {
+            let m = match match_type {
                 "exact" => Match::Exact {
-                    pattern: k.0.to_string(),
+                    pattern: pattern.to_string(),
                     value,
                 },
                 "generic" => Match::Generic {
-                    pattern: Pattern::try_from(k.0.to_string()).or(Err(ConfigError::new(
-                        format!("Invalid pattern given: {}", k.0),
-                    )))?,
+                    pattern: Pattern::try_from(pattern.to_string()).map_err(|e| {
+                        ConfigError::new(format!("Invalid pattern given: {pattern}: {e}"))
+                    })?,
 
                     value,
                 },
@@ -66,49 +108,413 @@ pub fn parse_content_to_config(content: &String) -> Result<Config, ConfigError>
         )))
     };
 
-    match map {
-        Value::Table(root) => {
-            let matching: Vec<Match> = root
-                .get("matching")
-                .ok_or(ConfigError::new("Matching table not found"))?
-                .as_table()
-                .ok_or(ConfigError::new("Could not parse matching table"))?
-                .iter()
-                .map(map_to_match)
-                .collect::<Result<Vec<Match>, ConfigError>>()?
-                .into_iter()
-                .collect();
-
-            let fallback: Option<String> = match root.get("fallback") {
-                Some(value) => {
-                    let f = value
-                        .as_str()
-                        .ok_or(ConfigError::new("Fallback is not a string"))?;
-                    Some(f.to_string())
+    let matching_table = root
+        .get("matching")
+        .ok_or(ConfigError::new("Matching table not found"))?
+        .as_table()
+        .ok_or(ConfigError::new("Could not parse matching table"))?;
+    let matching: Vec<Match> = matching_table
+        .iter()
+        .map(map_to_match)
+        .collect::<Result<Vec<Match>, ConfigError>>()?;
+    let styles: Vec<RuleStyle> = matching_table
+        .iter()
+        .map(|(k, v)| parse_rule_style(k, v))
+        .collect::<Result<Vec<RuleStyle>, ConfigError>>()?;
+
+    let fallback: Option<String> = match root.get("fallback") {
+        Some(value) => {
+            let f = value
+                .as_str()
+                .ok_or(ConfigError::new("Fallback is not a string"))?;
+            Some(resolve_icon_name(f)?)
+        }
+        None => None,
+    };
+
+    let separator: Option<String> = match root.get("separator") {
+        Some(value) => {
+            let f = value
+                .as_str()
+                .ok_or(ConfigError::new("Separator is not a string"))?;
+            Some(f.to_string())
+        }
+        None => None,
+    };
+
+    let parse_patterns = |value: &Value, name: &str| -> Result<Vec<Match>, ConfigError> {
+        value
+            .as_array()
+            .ok_or(ConfigError::new(format!("{name} is not an array")))?
+            .iter()
+            .map(|v| match v {
+                // Pattern lists are matches without a value
+                Value::String(pattern) => map_to_match((pattern, &Value::String(String::new()))),
+                Value::Table(table) => {
+                    let pattern = table
+                        .get("pattern")
+                        .and_then(|p| p.as_str())
+                        .ok_or(ConfigError::new(format!("No pattern given for: {v}")))?;
+                    let mut table = table.clone();
+                    table.insert("value".to_string(), Value::String(String::new()));
+                    map_to_match((&pattern.to_string(), &Value::Table(table)))
                 }
-                None => None,
-            };
+                _ => Err(ConfigError::new(format!("Invalid pattern in {name}: {v}"))),
+            })
+            .collect()
+    };
+
+    let ignore: Vec<Match> = match root.get("ignore") {
+        Some(value) => parse_patterns(value, "ignore")?,
+        None => vec![],
+    };
 
-            let separator: Option<String> = match root.get("separator") {
-                Some(value) => {
-                    let f = value
-                        .as_str()
-                        .ok_or(ConfigError::new("Separator is not a string"))?;
-                    Some(f.to_string())
+    let combinations: Vec<Combination> = match root.get("combine") {
+        Some(value) => value
+            .as_array()
+            .ok_or(ConfigError::new("Combine is not an array of tables"))?
+            .iter()
+            .map(|c| {
+                let table = c
+                    .as_table()
+                    .ok_or(ConfigError::new(format!("Combine {c} is not a table")))?;
+                let apps = match table.get("apps") {
+                    Some(apps) => parse_patterns(apps, "apps")?,
+                    None => vec![],
+                };
+                let icons = match table.get("icons") {
+                    Some(icons) => icons
+                        .as_array()
+                        .and_then(|icons| {
+                            icons
+                                .iter()
+                                .map(|i| i.as_str())
+                                .collect::<Option<Vec<&str>>>()
+                        })
+                        .ok_or(ConfigError::new(format!(
+                            "Icons of combine {c} is not an array of strings"
+                        )))?
+                        .into_iter()
+                        .map(resolve_icon_name)
+                        .collect::<Result<Vec<String>, ConfigError>>()?,
+                    None => vec![],
+                };
+                if apps.is_empty() && icons.is_empty() {
+                    return Err(ConfigError::new(format!(
+                        "Combine {c} needs at least one app or icon"
+                    )));
                 }
-                None => None,
-            };
+                let exclusive = match table.get("exclusive") {
+                    Some(e) => e.as_bool().ok_or(ConfigError::new(format!(
+                        "Exclusive of combine {c} is not a boolean"
+                    )))?,
+                    None => false,
+                };
+                let value = table
+                    .get("value")
+                    .and_then(|v| v.as_str())
+                    .ok_or(ConfigError::new(format!(
+                        "Value of combine {c} is not a string"
+                    )))?;
+                let value = resolve_icon_name(value)?;
 
-            Ok(Config {
-                matchings: matching,
-                fallback,
-                separator,
+                Ok(Combination {
+                    apps,
+                    icons,
+                    exclusive,
+                    value,
+                })
             })
+            .collect::<Result<Vec<Combination>, ConfigError>>()?,
+        None => vec![],
+    };
+
+    let workspaces: Vec<WorkspaceStyle> = match root.get("workspaces") {
+        Some(value) => value
+            .as_table()
+            .ok_or(ConfigError::new("Could not parse workspaces table"))?
+            .iter()
+            .map(|(k, v)| {
+                let num = k.parse().map_err(|_| {
+                    ConfigError::new(format!("Workspace {k} is not a workspace number"))
+                })?;
+                if let Some(value) = v.as_str() {
+                    return Ok(WorkspaceStyle {
+                        num,
+                        value: resolve_icon_name(value)?,
+                        show_empty: false,
+                    });
+                }
+                let table = v.as_table().ok_or(ConfigError::new(format!(
+                    "Workspace {k} is not a string or table"
+                )))?;
+                let value = table
+                    .get("value")
+                    .and_then(|v| v.as_str())
+                    .ok_or(ConfigError::new(format!(
+                        "Value of workspace {k} is not a string"
+                    )))?;
+                let value = resolve_icon_name(value)?;
+                let show_empty = match table.get("show_empty") {
+                    Some(s) => s.as_bool().ok_or(ConfigError::new(format!(
+                        "show_empty of workspace {k} is not a boolean"
+                    )))?,
+                    None => false,
+                };
+                Ok(WorkspaceStyle {
+                    num,
+                    value,
+                    show_empty,
+                })
+            })
+            .collect::<Result<Vec<WorkspaceStyle>, ConfigError>>()?,
+        None => vec![],
+    };
+
+    let exclude_workspaces: Vec<WorkspaceSelector> =
+        match root.get("exclude_workspaces") {
+            Some(value) => {
+                value
+                    .as_array()
+                    .ok_or(ConfigError::new("exclude_workspaces is not an array"))?
+                    .iter()
+                    .map(|w| match w {
+                        Value::Integer(num) => i32::try_from(*num).map(WorkspaceSelector::Num).or(
+                            Err(ConfigError::new(format!("Invalid workspace number: {w}"))),
+                        ),
+                        Value::String(name) => Ok(WorkspaceSelector::from(name.as_str())),
+                        _ => Err(ConfigError::new(format!("Invalid workspace: {w}"))),
+                    })
+                    .collect::<Result<Vec<WorkspaceSelector>, ConfigError>>()?
+            }
+            None => vec![],
+        };
+
+    let exclude_outputs: Vec<String> = match root.get("exclude_outputs") {
+        Some(value) => value
+            .as_array()
+            .and_then(|outputs| {
+                outputs
+                    .iter()
+                    .map(|o| o.as_str().map(|o| o.to_string()))
+                    .collect::<Option<Vec<String>>>()
+            })
+            .ok_or(ConfigError::new(
+                "exclude_outputs is not an array of strings",
+            ))?,
+        None => vec![],
+    };
+
+    let on_foreign_rename = match root.get("on_foreign_rename").map(|v| v.as_str()) {
+        Some(Some("overwrite")) | None => RenamePolicy::Overwrite,
+        Some(Some("respect")) => RenamePolicy::Respect,
+        Some(Some("back_off")) => RenamePolicy::BackOff,
+        Some(_) => {
+            return Err(ConfigError::new(
+                "on_foreign_rename is not one of \"overwrite\", \"respect\", \"back_off\"",
+            ))
         }
-        _ => Err(ConfigError::new("No root table found")),
+    };
+
+    let icon_set = match root.get("icon_set").map(|v| v.as_str()) {
+        Some(Some("fontawesome")) | None => IconSet::FontAwesome,
+        Some(Some("nerdfont")) => IconSet::NerdFont,
+        Some(Some("emoji")) => IconSet::Emoji,
+        Some(Some("ascii")) => IconSet::Ascii,
+        Some(_) => {
+            return Err(ConfigError::new(
+                "icon_set is not one of \"fontawesome\", \"nerdfont\", \"emoji\", \"ascii\"",
+            ))
+        }
+    };
+
+    let display = match root.get("display").map(|v| v.as_str()) {
+        Some(Some("icon")) | None => DisplayMode::Icon,
+        Some(Some("label")) => DisplayMode::Label,
+        Some(Some("both")) => DisplayMode::Both,
+        Some(_) => {
+            return Err(ConfigError::new(
+                "display is not one of \"icon\", \"label\", \"both\"",
+            ))
+        }
+    };
+
+    let markup = match root.get("markup") {
+        Some(value) => value
+            .as_bool()
+            .ok_or(ConfigError::new("markup is not a boolean"))?,
+        None => false,
+    };
+
+    let fallback_chain = match root.get("fallback_chain") {
+        Some(value) => parse_fallback_chain(value)?,
+        None => FallbackChain::default(),
+    };
+
+    let use_defaults = match root.get("use_defaults") {
+        Some(value) => value
+            .as_bool()
+            .ok_or(ConfigError::new("use_defaults is not a boolean"))?,
+        None => true,
+    };
+
+    let disable_defaults: Vec<String> = match root.get("disable_defaults") {
+        Some(value) => value
+            .as_array()
+            .and_then(|patterns| {
+                patterns
+                    .iter()
+                    .map(|p| p.as_str().map(|p| p.to_string()))
+                    .collect::<Option<Vec<String>>>()
+            })
+            .ok_or(ConfigError::new(
+                "disable_defaults is not an array of strings",
+            ))?,
+        None => vec![],
+    };
+
+    let ignore_floating = match root.get("ignore_floating") {
+        Some(value) => value
+            .as_bool()
+            .ok_or(ConfigError::new("ignore_floating is not a boolean"))?,
+        None => false,
+    };
+
+    let ignore_smaller_than = match root.get("ignore_smaller_than") {
+        Some(value) => {
+            let size = value
+                .as_array()
+                .filter(|a| a.len() == 2)
+                .ok_or(ConfigError::new(
+                    "ignore_smaller_than is not an array of [width, height]",
+                ))?;
+            Some((parse_size(&size[0])?, parse_size(&size[1])?))
+        }
+        None => None,
+    };
+
+    let max_icons = parse_usize(root, "max_icons")?;
+    let max_width = parse_usize(root, "max_width")?;
+
+    Ok(Config {
+        matchings: matching,
+        fallback,
+        separator,
+        max_icons,
+        max_width,
+        ignore,
+        ignore_floating,
+        ignore_smaller_than,
+        combinations,
+        workspaces,
+        exclude_workspaces,
+        exclude_outputs,
+        on_foreign_rename,
+        icon_set,
+        display,
+        markup,
+        fallback_chain,
+        use_defaults,
+        disable_defaults,
+        files: vec![],
+        sources: vec![],
+        styles,
+        templates: vec![],
+        desktop_entries: Default::default(),
+    })
+}
+
+/// Parse the stages of the fallback chain, stages left out keep their default
+fn parse_fallback_chain(value: &Value) -> Result<FallbackChain, ConfigError> {
+    let table = value
+        .as_table()
+        .ok_or(ConfigError::new("fallback_chain is not a table"))?;
+
+    let mut chain = FallbackChain::default();
+    for (key, value) in table {
+        let stage = match key.as_str() {
+            "desktop_entry" => &mut chain.desktop_entry,
+            "category" => &mut chain.category,
+            "first_letter" => &mut chain.first_letter,
+            _ => {
+                return Err(ConfigError::new(format!(
+                    "Unknown fallback_chain stage \"{key}\", use one of \"desktop_entry\", \"category\", \"first_letter\""
+                )))
+            }
+        };
+        *stage = value.as_bool().ok_or(ConfigError::new(format!(
+            "fallback_chain.{key} is not a boolean"
+        )))?;
+    }
+
+    Ok(chain)
+}
+
+/// Parse the options of a rule in the verbose format
+fn parse_rule_style(pattern: &str, value: &Value) -> Result<RuleStyle, ConfigError> {
+    let Some(table) = value.as_table() else {
+        return Ok(RuleStyle::default());
+    };
+
+    let string = |key: &str| -> Result<Option<String>, ConfigError> {
+        match table.get(key) {
+            Some(value) => Ok(Some(
+                value
+                    .as_str()
+                    .ok_or(ConfigError::new(format!(
+                        "{key} of {pattern} is not a string"
+                    )))?
+                    .to_string(),
+            )),
+            None => Ok(None),
+        }
+    };
+
+    // Sizes without a unit are in points
+    let size = match table.get("size") {
+        Some(Value::Integer(size)) => Some(format!("{size}pt")),
+        Some(Value::String(size)) => Some(size.clone()),
+        Some(_) => {
+            return Err(ConfigError::new(format!(
+                "size of {pattern} is not a string or integer"
+            )))
+        }
+        None => None,
+    };
+
+    Ok(RuleStyle {
+        label: string("label")?,
+        color: string("color")?,
+        font: string("font")?,
+        size,
+    })
+}
+
+/// Parse an optional positive integer from the root table
+fn parse_usize(root: &Table, key: &str) -> Result<Option<usize>, ConfigError> {
+    match root.get(key) {
+        Some(value) => {
+            let n = value
+                .as_integer()
+                .and_then(|n| usize::try_from(n).ok())
+                .ok_or(ConfigError::new(format!("{key} is not a positive integer")))?;
+            Ok(Some(n))
+        }
+        None => Ok(None),
     }
 }
 
+/// Parse a width or height of `ignore_smaller_than`
+pub(super) fn parse_size(value: &Value) -> Result<i32, ConfigError> {
+    value
+        .as_integer()
+        .and_then(|n| i32::try_from(n).ok())
+        .filter(|n| *n >= 0)
+        .ok_or(ConfigError::new(format!(
+            "ignore_smaller_than size {value} is not a positive integer"
+        )))
+}
+
 #[test]
 fn test_parse_content_to_config() {
     use regex::Regex;
@@ -124,7 +530,7 @@ fn test_parse_content_to_config() {
     [matching]
     a = b
     ";
-    let invalid_match = parse_content_to_config(&content.to_string());
+    let invalid_match = parse_content_to_config(content);
     let e = invalid_match.unwrap_err();
     assert!(
         e.to_string().starts_with("TOML parse error"),
@@ -139,7 +545,7 @@ fn test_parse_content_to_config() {
     test = { type = 'generic', value = 'c' }
     qwer = { type = 'exact', value = 'd' }
     ";
-    let icon_map = parse_content_to_config(&content.to_string()).unwrap();
+    let icon_map = parse_content_to_config(content).unwrap();
 
     assert_eq!(
         icon_map.matchings[0],
@@ -162,6 +568,7 @@ fn test_parse_content_to_config() {
             pattern: Pattern::String("test".to_string())
         }
     );
+    assert_eq!(icon_map.on_foreign_rename, RenamePolicy::Overwrite);
     assert_eq!(
         icon_map.matchings[3],
         Match::Exact {
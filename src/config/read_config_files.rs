@@ -0,0 +1,266 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{read_dir, read_to_string},
+    path::{Path, PathBuf},
+};
+
+use toml::{Table, Value};
+
+use super::config_error::ConfigError;
+
+/// Options that are lists of entries, these are appended when merging files
+pub const LIST_KEYS: &[&str] = &[
+    "ignore",
+    "combine",
+    "exclude_workspaces",
+    "exclude_outputs",
+    "disable_defaults",
+];
+
+/// Merged content of config files
+#[derive(Default)]
+pub struct ConfigFiles {
+    pub table: Table,
+    /// All files that have been read
+    pub files: Vec<PathBuf>,
+    /// File each matching has been taken from by pattern
+    pub matching_sources: HashMap<String, PathBuf>,
+}
+
+/// Get the xdg default config paths, the user config followed by the system configs
+pub fn default_config_paths() -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if let Some(config_dir) = dirs::config_dir() {
+        paths.push(config_dir.join("sworkstyle/config.toml"));
+    }
+
+    let config_dirs = env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    paths.extend(
+        config_dirs
+            .split(':')
+            .map(PathBuf::from)
+            // Relative paths are invalid and should be ignored
+            .filter(|dir| dir.is_absolute())
+            .map(|dir| dir.join("sworkstyle/config.toml")),
+    );
+
+    paths
+}
+
+/// Read a config file together with its includes and the `conf.d` drop-in files next to it
+/// and merge it into `files`.
+///
+/// Files are merged in order: the file itself, its includes in the order they are listed
+/// and then the drop-in files sorted by name, each followed by their own includes.
+/// Matchings and values of earlier files take precedence over later ones, lists are appended.
+pub fn read_config_files(path: &Path, files: &mut ConfigFiles) -> Result<(), ConfigError> {
+    read_layer(path, conf_d_dir(path), files)
+}
+
+fn read_layer(
+    path: &Path,
+    conf_d: Option<PathBuf>,
+    files: &mut ConfigFiles,
+) -> Result<(), ConfigError> {
+    let root = read_with_includes(path, files, &mut vec![])?;
+    merge_tables(&mut files.table, root);
+
+    if let Some(dir) = conf_d {
+        let mut drop_ins: Vec<PathBuf> = match read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+                .collect(),
+            Err(_) => vec![],
+        };
+        drop_ins.sort();
+
+        for drop_in in drop_ins {
+            let table = read_with_includes(&drop_in, files, &mut vec![])?;
+            merge_tables(&mut files.table, table);
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the drop-in directory belonging to a config file. Only the default config files
+/// have one, a file given on the command line is read on its own.
+pub fn conf_d_dir(path: &Path) -> Option<PathBuf> {
+    if !default_config_paths().iter().any(|p| p == path) {
+        return None;
+    }
+    Some(path.parent()?.join("conf.d"))
+}
+
+/// Read a file and the files it includes, `stack` being the files including it
+fn read_with_includes(
+    path: &Path,
+    files: &mut ConfigFiles,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Table, ConfigError> {
+    if stack.iter().any(|f| f == path) {
+        return Err(ConfigError::new(format!("{path:?} includes itself")));
+    }
+    // Included through another file before, which takes precedence over this include
+    if files.files.iter().any(|f| f == path) {
+        return Ok(Table::new());
+    }
+    files.files.push(path.to_path_buf());
+
+    let content = read_to_string(path)
+        .map_err(|e| ConfigError::new(format!("Could not read {path:?}: {e}")))?;
+    let mut root: Table =
+        toml::from_str(&content).map_err(|e| ConfigError::new(format!("{path:?}: {e}")))?;
+
+    let includes = match root.remove("include") {
+        Some(Value::Array(includes)) => includes,
+        Some(_) => {
+            return Err(ConfigError::new(format!(
+                "{path:?}: include is not an array of paths"
+            )))
+        }
+        None => vec![],
+    };
+
+    if let Some(Value::Table(matching)) = root.get("matching") {
+        for key in matching.keys() {
+            files
+                .matching_sources
+                .entry(key.clone())
+                .or_insert(path.to_path_buf());
+        }
+    }
+
+    for include in includes {
+        let include = include.as_str().ok_or(ConfigError::new(format!(
+            "{path:?}: include {include} is not a path"
+        )))?;
+        stack.push(path.to_path_buf());
+        let table = read_with_includes(&resolve_include(path, include), files, stack);
+        stack.pop();
+        merge_tables(&mut root, table?);
+    }
+
+    Ok(root)
+}
+
+/// Expand `~` and resolve relative paths against the directory of the including file
+pub fn resolve_include(path: &Path, include: &str) -> PathBuf {
+    if let Some(rest) = include.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+
+    match path.parent() {
+        Some(dir) => dir.join(include),
+        None => PathBuf::from(include),
+    }
+}
+
+/// Merge `other` into `base`, keeping the values of `base`.
+/// List options are appended and tables (like `matching`) get the keys missing in `base`.
+pub fn merge_tables(base: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (base.get_mut(&key), value) {
+            (None, value) => {
+                base.insert(key, value);
+            }
+            (Some(Value::Array(base)), Value::Array(mut other))
+                if LIST_KEYS.contains(&key.as_str()) =>
+            {
+                base.append(&mut other)
+            }
+            (Some(Value::Table(base)), Value::Table(other)) => {
+                for (key, value) in other {
+                    base.entry(key).or_insert(value);
+                }
+            }
+            (Some(_), _) => {}
+        }
+    }
+}
+
+#[test]
+fn test_merge_tables() {
+    let mut base: Table = toml::from_str(
+        "
+    fallback = 'a'
+    ignore = ['x']
+    ignore_smaller_than = [10, 20]
+    [matching]
+    a = 'b'
+    ",
+    )
+    .unwrap();
+    let other: Table = toml::from_str(
+        "
+    fallback = 'b'
+    separator = '|'
+    ignore = ['y']
+    ignore_smaller_than = [30, 40]
+    [matching]
+    a = 'c'
+    d = 'e'
+    ",
+    )
+    .unwrap();
+    merge_tables(&mut base, other);
+
+    assert_eq!(base["fallback"].as_str(), Some("a"));
+    assert_eq!(base["separator"].as_str(), Some("|"));
+    assert_eq!(base["ignore"].as_array().unwrap().len(), 2);
+    // A size is a single value, not a list
+    assert_eq!(base["ignore_smaller_than"], Value::from(vec![10, 20]));
+    let matching = base["matching"].as_table().unwrap();
+    assert_eq!(matching["a"].as_str(), Some("b"));
+    assert_eq!(matching.keys().collect::<Vec<&String>>(), vec!["a", "d"]);
+}
+
+#[test]
+fn test_read_layer() {
+    let dir = crate::util::TestDir::new("read-layer");
+    // Both include common.toml
+    dir.write(
+        "config.toml",
+        "include = ['a.toml', 'b.toml']\n[matching]\n'app' = 'user'",
+    );
+    dir.write("a.toml", "include = ['common.toml']");
+    dir.write("b.toml", "include = ['common.toml']");
+    dir.write("common.toml", "ignore = ['x']\n[matching]\n'common' = 'c'");
+    dir.write("conf.d/10-extra.toml", "[matching]\n'extra' = 'e'");
+    dir.write("cycle.toml", "include = ['cycle-2.toml']");
+    dir.write("cycle-2.toml", "include = ['cycle.toml']");
+
+    let mut files = ConfigFiles::default();
+    let result = read_layer(
+        &dir.join("config.toml"),
+        Some(dir.join("conf.d")),
+        &mut files,
+    );
+    let cycle = read_config_files(&dir.join("cycle.toml"), &mut ConfigFiles::default());
+    let mut without_conf_d = ConfigFiles::default();
+    read_config_files(&dir.join("config.toml"), &mut without_conf_d).unwrap();
+
+    result.unwrap();
+    let matching = files.table["matching"].as_table().unwrap();
+    assert_eq!(
+        matching.keys().collect::<Vec<&String>>(),
+        vec!["app", "common", "extra"]
+    );
+    assert_eq!(files.table["ignore"].as_array().unwrap().len(), 1);
+    assert_eq!(files.files.len(), 5);
+
+    assert!(cycle.unwrap_err().to_string().contains("includes itself"));
+    // Not a default config file, so there is no conf.d
+    assert!(!without_conf_d.table["matching"]
+        .as_table()
+        .unwrap()
+        .contains_key("extra"));
+}
@@ -0,0 +1,188 @@
+use regex::Captures;
+
+/// Window fields that can be used in values
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Window<'a> {
+    pub app_id: Option<&'a str>,
+    pub class: Option<&'a str>,
+    pub title: Option<&'a str>,
+}
+
+impl<'a> Window<'a> {
+    /// The name used for exact matching, X11 class takes precedence over the wayland app id
+    pub fn exact_name(&self) -> Option<&'a str> {
+        self.class.or(self.app_id)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Field {
+    AppId,
+    Class,
+    Title,
+    /// Capture group of a title regex
+    Group(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field { field: Field, max: Option<usize> },
+}
+
+/// A value with placeholders like `{title:12}`, `{app_id}` or `$1`.
+/// Literal braces are written as `{{` and `}}`. Values without any placeholder, like icons
+/// that happen to contain a brace, are used as written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parse a value of a rule of which the pattern has `groups` capture groups, counting
+    /// the whole match. Anything that is not a valid placeholder is kept as text.
+    pub fn parse(value: &str, groups: usize) -> Template {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = value.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '$' if chars
+                    .peek()
+                    .and_then(|c| c.to_digit(10))
+                    .is_some_and(|group| (group as usize) < groups) =>
+                {
+                    let group = chars.next().and_then(|c| c.to_digit(10)).unwrap_or(0);
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                    parts.push(Part::Field {
+                        field: Field::Group(group as usize),
+                        max: None,
+                    });
+                }
+                '{' => {
+                    let rest: String = chars.clone().collect();
+                    match rest
+                        .split_once('}')
+                        .and_then(|(placeholder, _)| parse_placeholder(placeholder, groups))
+                    {
+                        Some((part, len)) => {
+                            chars.nth(len);
+                            parts.push(Part::Text(std::mem::take(&mut text)));
+                            parts.push(part);
+                        }
+                        None => text.push('{'),
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+        parts.push(Part::Text(text));
+        parts.retain(|p| *p != Part::Text(String::new()));
+
+        if !parts.iter().any(|p| matches!(p, Part::Field { .. })) {
+            return Template {
+                parts: vec![Part::Text(value.to_string())],
+            };
+        }
+        Template { parts }
+    }
+
+    /// Fill in the placeholders, missing fields and groups are left empty
+    pub fn render(&self, window: &Window, captures: Option<&Captures>) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field { field, max } => {
+                    let value = match field {
+                        Field::AppId => window.app_id,
+                        Field::Class => window.class,
+                        Field::Title => window.title,
+                        Field::Group(i) => captures.and_then(|c| c.get(*i)).map(|m| m.as_str()),
+                    };
+                    truncate(&sanitize(value.unwrap_or_default()), *max)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Parse the inside of a placeholder together with its length in chars
+fn parse_placeholder(placeholder: &str, groups: usize) -> Option<(Part, usize)> {
+    let (name, max) = match placeholder.split_once(':') {
+        Some((name, max)) => (name.trim(), Some(max.trim().parse().ok()?)),
+        None => (placeholder.trim(), None),
+    };
+
+    let field = match name {
+        "app_id" => Field::AppId,
+        "class" => Field::Class,
+        "title" => Field::Title,
+        group => match group.parse() {
+            Ok(group) if group < groups => Field::Group(group),
+            _ => return None,
+        },
+    };
+
+    Some((Part::Field { field, max }, placeholder.chars().count()))
+}
+
+/// Make window text fit in a workspace name by removing control characters and
+/// collapsing whitespace
+fn sanitize(s: &str) -> String {
+    s.split(|c: char| c.is_whitespace() || c.is_control())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Shorten to at most `max` characters, ending with an ellipsis when shortened
+fn truncate(s: &str, max: Option<usize>) -> String {
+    match max {
+        Some(max) if s.chars().count() > max => {
+            let mut s: String = s.chars().take(max.saturating_sub(1)).collect();
+            if max > 0 {
+                s.push('…');
+            }
+            s
+        }
+        _ => s.to_string(),
+    }
+}
+
+#[test]
+fn test_template() {
+    use regex::Regex;
+
+    let window = Window {
+        app_id: Some("foot"),
+        class: None,
+        title: Some("sworkstyle - nvim\n"),
+    };
+    let regex = Regex::new(r"^(\w+) - nvim").unwrap();
+    let captures = regex.captures(window.title.unwrap());
+
+    let render = |value: &str| {
+        Template::parse(value, regex.captures_len()).render(&window, captures.as_ref())
+    };
+    assert_eq!(render(" $1"), " sworkstyle");
+    assert_eq!(render("{1:5}"), "swor…");
+    assert_eq!(render("{app_id}/{class}"), "foot/");
+    assert_eq!(render("{title}"), "sworkstyle - nvim");
+    assert_eq!(render("{{literal}} {app_id} $"), "{literal} foot $");
+    assert_eq!(
+        render("{name} {title:x} {app_id"),
+        "{name} {title:x} {app_id"
+    );
+    assert_eq!(render("{{}}"), "{{}}");
+    assert_eq!(render("$2 {2}"), "$2 {2}");
+}
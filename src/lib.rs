@@ -2,40 +2,173 @@ use futures_lite::prelude::*;
 
 use async_io::Async;
 use futures_lite::stream;
-use inotify::{Inotify, WatchMask};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
 use std::{
-    collections::BTreeSet,
+    collections::{HashMap, HashSet, VecDeque},
     error::Error,
+    fs, io,
+    os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
 };
 
 use log::{debug, error, info, warn};
-use swayipc_async::{Connection, Event, EventType, Node, NodeType, WindowChange};
+use swayipc_async::{Connection, Event, EventType, Node, NodeType, WindowChange, WorkspaceChange};
+use unicode_width::UnicodeWidthStr;
 
 pub mod config;
+pub mod unmatched;
 mod util;
 
-use config::Config;
+use config::{conf_d_dir, Config, ConfigError, RenamePolicy, RuleStyle, Window};
+use unmatched::UnmatchedWindows;
 
 pub type SworkstyleError = Box<dyn Error>;
 
+/// Names kept per workspace while waiting for the rename events of our own renames
+const MAX_PENDING_NAMES: usize = 8;
+
+/// Watches the directories of the config files instead of the files themselves,
+/// so reloading keeps working when editors replace a file or it gets re-created.
+/// For symlinked files the directory of the target is watched as well.
 struct ConfigSource {
-    path: PathBuf,
+    /// Config layers
+    paths: Vec<PathBuf>,
     inotify: Inotify,
+    /// Watched directories
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    /// Watched files, being the config layers and their includes
+    files: Vec<PathBuf>,
 }
 
 impl ConfigSource {
-    fn new(path: impl AsRef<Path>) -> ConfigSource {
-        let inotify = Inotify::init().expect("Error while initializing inotify instance");
-        inotify
-            .watches()
-            .add(&path, WatchMask::CLOSE_WRITE)
-            .expect("Failed to watch config file");
-
-        ConfigSource {
-            path: path.as_ref().to_path_buf(),
+    fn new<P: AsRef<Path>>(paths: &[P]) -> Option<ConfigSource> {
+        let paths: Vec<PathBuf> = paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        let inotify = Inotify::init()
+            .map_err(|e| error!("Error while initializing inotify instance: {e}"))
+            .ok()?;
+
+        let mut source = ConfigSource {
+            paths: paths.clone(),
             inotify,
+            dirs: HashMap::new(),
+            files: vec![],
+        };
+        source.watch(&paths);
+        if source.dirs.is_empty() {
+            return None;
+        }
+        for conf_d in paths.iter().filter_map(|p| conf_d_dir(p)) {
+            source.watch_dir(&conf_d);
+        }
+
+        Some(source)
+    }
+
+    /// Watch the directories of `files` in addition to the config layers
+    fn watch(&mut self, files: &[PathBuf]) {
+        for file in files {
+            // Edits to the target of a symlink, like one set up by a dotfile manager, only
+            // show up in the directory of the target
+            let target = fs::canonicalize(file).ok().filter(|target| target != file);
+            for file in [Some(file.clone()), target].into_iter().flatten() {
+                if self.files.contains(&file) {
+                    continue;
+                }
+                let dir = match file.parent() {
+                    Some(dir) if dir.as_os_str().is_empty() => PathBuf::from("."),
+                    Some(dir) => dir.to_path_buf(),
+                    None => continue,
+                };
+                self.watch_dir(&dir);
+                self.files.push(file);
+            }
+        }
+    }
+
+    fn watch_dir(&mut self, dir: &Path) {
+        if self.dirs.values().any(|d| d == dir) || !dir.exists() {
+            return;
+        }
+        match self.inotify.watches().add(
+            dir,
+            WatchMask::CREATE
+                | WatchMask::MOVED_TO
+                | WatchMask::CLOSE_WRITE
+                | WatchMask::DELETE
+                | WatchMask::MOVED_FROM,
+        ) {
+            Ok(wd) => {
+                self.dirs.insert(wd, dir.to_path_buf());
+            }
+            Err(e) => error!("Failed to watch config directory {dir:?}: {e}"),
+        }
+    }
+
+    /// Check if a file is part of the config
+    fn is_config_file(&self, file: &Path) -> bool {
+        self.files.iter().any(|f| f == file)
+            || (file.extension().is_some_and(|e| e == "toml")
+                && self
+                    .paths
+                    .iter()
+                    .any(|p| file.parent() == conf_d_dir(p).as_deref()))
+    }
+
+    /// Wait until a config file has been written or moved into place
+    async fn changed(mut self) -> io::Result<ConfigSource> {
+        let mut buffer = [0; 1024];
+
+        loop {
+            let anotify = Async::new(self.inotify)?;
+            anotify.readable().await?;
+            self.inotify = anotify.into_inner()?;
+
+            let events: Vec<(PathBuf, EventMask)> = self
+                .inotify
+                .read_events(&mut buffer)?
+                .filter_map(|e| Some((self.dirs.get(&e.wd)?.join(e.name?), e.mask)))
+                .collect();
+
+            // Start watching drop-in directories once they get created
+            for conf_d in self.paths.clone().iter().filter_map(|p| conf_d_dir(p)) {
+                if events.iter().any(|(file, mask)| {
+                    *file == conf_d && mask.intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                }) {
+                    self.watch_dir(&conf_d);
+                }
+            }
+
+            // Only the last event for a config file matters
+            let last = events
+                .into_iter()
+                .rfind(|(file, _)| self.is_config_file(file));
+
+            if let Some((file, mask)) = last {
+                if self.needs_reload(&file, mask) {
+                    return Ok(self);
+                }
+            }
+        }
+    }
+
+    /// Check if an event for a config file changes the config
+    fn needs_reload(&self, file: &Path, mask: EventMask) -> bool {
+        if mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM) {
+            // Likely about to be replaced, without the layer itself there's nothing to load
+            if self.paths.iter().any(|p| p == file) {
+                info!("Config file {file:?} was removed, keeping the current config");
+                return false;
+            }
+            // Rules of a removed include or drop-in file no longer apply
+            return true;
+        }
+        if mask.contains(EventMask::CREATE) {
+            // A link to an existing file, like one set up by a dotfile manager, is complete
+            // when created. Other files have to be written first.
+            return fs::symlink_metadata(file)
+                .is_ok_and(|m| m.file_type().is_symlink() || m.nlink() > 1);
         }
+        true
     }
 }
 
@@ -43,30 +176,83 @@ pub struct Sworkstyle {
     config: Config,
     config_source: Option<ConfigSource>,
     deduplicate: bool,
+    /// Names set by sworkstyle per workspace id, for which no rename event has been received yet
+    pending_names: HashMap<i64, VecDeque<String>>,
+    /// Labels of workspaces that got renamed by someone else
+    labels: HashMap<i64, String>,
+    /// Workspaces that got renamed by someone else and are no longer renamed
+    backed_off: HashSet<i64>,
+    /// Records windows without a matching rule when learn mode is enabled
+    unmatched: Option<UnmatchedWindows>,
 }
 
 impl Sworkstyle {
     pub fn new<P: AsRef<Path>>(config_path: Option<P>, deduplicate: bool) -> Sworkstyle {
-        let config = Config::new(&config_path);
-        let config_source =
-            config_path.and_then(|path| path.as_ref().exists().then(|| ConfigSource::new(path)));
+        Sworkstyle::with_layers(config_path.as_slice(), deduplicate)
+    }
+
+    /// Like `new` but fails when an existing config file can not be loaded
+    pub fn try_new<P: AsRef<Path>>(
+        config_path: Option<P>,
+        deduplicate: bool,
+    ) -> Result<Sworkstyle, SworkstyleError> {
+        Sworkstyle::try_with_layers(config_path.as_slice(), deduplicate)
+    }
+
+    /// Create with config files layered on top of each other, earlier files take precedence
+    pub fn with_layers<P: AsRef<Path>>(config_paths: &[P], deduplicate: bool) -> Sworkstyle {
+        let mut config = Config::with_layers(config_paths);
+        config.load_desktop_entries();
+        Sworkstyle::with_config(config, config_paths, deduplicate)
+    }
+
+    /// Like `with_layers` but fails when existing config files can not be loaded
+    pub fn try_with_layers<P: AsRef<Path>>(
+        config_paths: &[P],
+        deduplicate: bool,
+    ) -> Result<Sworkstyle, SworkstyleError> {
+        let mut config = Config::try_with_layers(config_paths)
+            .map_err(|e| format!("Could not load config: {e}"))?;
+        config.load_desktop_entries();
+        Ok(Sworkstyle::with_config(config, config_paths, deduplicate))
+    }
+
+    fn with_config<P: AsRef<Path>>(
+        config: Config,
+        config_paths: &[P],
+        deduplicate: bool,
+    ) -> Sworkstyle {
+        let config_source = ConfigSource::new(config_paths).map(|mut source| {
+            source.watch(&config.files);
+            source
+        });
         Sworkstyle {
             config,
             config_source,
             deduplicate,
+            pending_names: HashMap::new(),
+            labels: HashMap::new(),
+            backed_off: HashSet::new(),
+            unmatched: None,
         }
     }
 
+    /// Record windows for which no rule matched in `unmatched`
+    pub fn with_learn_mode(mut self, unmatched: UnmatchedWindows) -> Sworkstyle {
+        self.unmatched = Some(unmatched);
+        self
+    }
+
     // Takes `self` by value because we consume `config_source`.
     pub async fn run(mut self) -> Result<(), SworkstyleError> {
         enum Message {
             Event(Event),
-            Config(Config),
+            Config(Result<Box<Config>, ConfigError>),
         }
 
         let mut events = Connection::new()
             .await?
-            .subscribe(&[EventType::Window])
+            .subscribe(&[EventType::Window, EventType::Workspace])
             .await?
             .map(|r| r.map(Message::Event))
             .boxed();
@@ -75,24 +261,16 @@ impl Sworkstyle {
         if let Some(source) = self.config_source.take() {
             events = events
                 .or(stream::try_unfold(source, |source| async {
-                    let path = source.path;
-                    let anotify = Async::new(source.inotify)?;
-                    anotify.readable().await?;
-                    let mut inotify = anotify.into_inner()?;
-                    let mut inotify_events_buffer = [0; 1024];
-                    inotify.read_events(&mut inotify_events_buffer)?;
+                    let mut source = source.changed().await?;
                     info!("Detected config change, reloading config..");
-                    let config = Config::new(&Some(&path));
-                    // Reset watcher
-                    inotify
-                        .watches()
-                        .add(&path, WatchMask::CLOSE_WRITE)
-                        .expect("Failed to watch config file");
-
-                    Ok(Some((
-                        Message::Config(config),
-                        ConfigSource { path, inotify },
-                    )))
+                    let mut config = Config::load_layers(&source.paths);
+                    if let Ok(config) = &mut config {
+                        source.watch(&config.files);
+                        // Picks up apps installed since the last load
+                        config.load_desktop_entries();
+                    }
+
+                    Ok(Some((Message::Config(config.map(Box::new)), source)))
                 }))
                 .boxed();
         }
@@ -108,18 +286,41 @@ impl Sworkstyle {
                         e.change,
                         WindowChange::Focus
                             | WindowChange::FullscreenMode
-                            | WindowChange::Floating
                             | WindowChange::Urgent
                             | WindowChange::Mark
-                    ) {
+                    ) || (e.change == WindowChange::Floating && !self.config.ignore_floating)
+                    {
                         // Event not relevant to us: skip the update_workspaces_call below.
                         continue;
                     }
                 }
-                // Should not be reachable: we are only subscribed to window events.
+                Ok(Message::Event(Event::Workspace(e))) => match (e.change, e.current) {
+                    // New workspaces need a name, for example a fixed workspace value
+                    (WorkspaceChange::Init, _) => {}
+                    (WorkspaceChange::Rename, Some(workspace)) => {
+                        let name = workspace.name.as_deref().unwrap_or_default();
+                        let output = workspace.output.as_deref();
+                        if !self.handle_rename(workspace.id, workspace.num, name, output) {
+                            continue;
+                        }
+                    }
+                    (WorkspaceChange::Empty, Some(workspace)) => {
+                        // Workspace is destroyed, its id won't be reused
+                        self.pending_names.remove(&workspace.id);
+                        self.labels.remove(&workspace.id);
+                        self.backed_off.remove(&workspace.id);
+                        continue;
+                    }
+                    _ => continue,
+                },
+                // Should not be reachable: we are only subscribed to window and workspace events.
                 Ok(Message::Event(_)) => {}
-                Ok(Message::Config(config)) => {
-                    self.config = config;
+                Ok(Message::Config(Ok(config))) => {
+                    self.config = *config;
+                }
+                Ok(Message::Config(Err(e))) => {
+                    error!("Invalid config, keeping the previous config: {e}");
+                    continue;
                 }
                 Err(e) => {
                     warn!("Error while waiting for Sway or config events, exiting: {e}");
@@ -134,11 +335,63 @@ impl Sworkstyle {
         Ok(())
     }
 
-    async fn update_workspaces(&self, conn: &mut Connection) -> Result<(), SworkstyleError> {
+    /// Handle a rename event, returns if the workspaces should be updated
+    fn handle_rename(
+        &mut self,
+        id: i64,
+        num: Option<i32>,
+        name: &str,
+        output: Option<&str>,
+    ) -> bool {
+        // Check if this is one of our own renames
+        if let Some(pending) = self.pending_names.get_mut(&id) {
+            if let Some(i) = pending.iter().position(|n| *n == name) {
+                pending.drain(..=i);
+                return false;
+            }
+        }
+        // Never renamed by us, so any name is fine
+        if self.is_workspace_excluded(id, num, name, output) {
+            return false;
+        }
+
+        match self.config.on_foreign_rename {
+            // Renaming right away could start a loop with a program reacting to renames
+            RenamePolicy::Overwrite => {
+                info!("Workspace was renamed to \"{name}\" by someone else, overwriting it on the next window event");
+                false
+            }
+            RenamePolicy::Respect => {
+                // Strip the workspace number, the rest is used as label
+                let label = match num {
+                    Some(num) => name.strip_prefix(&num.to_string()).unwrap_or(name),
+                    None => name,
+                };
+                let label = label.trim_start_matches(':').trim();
+                info!("Workspace was renamed to \"{name}\" by someone else, using \"{label}\" as label");
+                if label.is_empty() {
+                    self.labels.remove(&id);
+                } else {
+                    self.labels.insert(id, label.to_string());
+                }
+                true
+            }
+            RenamePolicy::BackOff => {
+                warn!("Workspace was renamed to \"{name}\" by someone else, no longer renaming it");
+                self.backed_off.insert(id);
+                false
+            }
+        }
+    }
+
+    async fn update_workspaces(&mut self, conn: &mut Connection) -> Result<(), SworkstyleError> {
         let tree = conn.get_tree().await?;
 
         let mut workspaces = vec![];
-        get_workspaces_recurse(&tree, &mut workspaces);
+        get_workspaces_recurse(&tree, None, &mut workspaces, &|workspace, output| {
+            let name = workspace.name.as_deref().unwrap_or_default();
+            self.is_workspace_excluded(workspace.id, workspace.num, name, output)
+        });
 
         for workspace in workspaces {
             self.update_workspace_name(conn, workspace).await?;
@@ -147,64 +400,100 @@ impl Sworkstyle {
         Ok(())
     }
 
-    async fn update_workspace_name(
+    /// Check if a workspace is never renamed, either by the config or because we backed off
+    fn is_workspace_excluded(
         &self,
+        id: i64,
+        num: Option<i32>,
+        name: &str,
+        output: Option<&str>,
+    ) -> bool {
+        name == "__i3_scratch"
+            || self.backed_off.contains(&id)
+            || self.config.is_workspace_excluded(num, name, output)
+    }
+
+    fn is_window_ignored(&self, node: &Node) -> bool {
+        let config = &self.config;
+        if config.ignore_floating && node.node_type == NodeType::FloatingCon {
+            return true;
+        }
+        if let Some((width, height)) = config.ignore_smaller_than {
+            if node.rect.width < width || node.rect.height < height {
+                return true;
+            }
+        }
+        let exact_name = exact_name(node).map(|n| n.as_str()).unwrap_or_default();
+        config.is_ignored(exact_name, node.name.as_ref())
+    }
+
+    async fn update_workspace_name(
+        &mut self,
         conn: &mut Connection,
         workspace: &Node,
     ) -> Result<(), SworkstyleError> {
         let mut windows = vec![];
         get_windows(workspace, &mut windows);
 
-        let mut window_names: Vec<(Option<&String>, Option<String>)> = windows
-            .iter()
-            .map(|node| {
-                let mut exact_name: Option<&String> = None;
+        windows.retain(|node| !self.is_window_ignored(node));
 
-                // Wayland Exact app
-                if let Some(app_id) = &node.app_id {
-                    exact_name = Some(app_id);
-                }
+        // Sorting puts windows with the same icon next to each other for deduplicating the icons
+        if self.deduplicate {
+            fn key(node: &Node) -> (Option<&String>, Option<&String>) {
+                (exact_name(node), node.name.as_ref())
+            }
+            windows.sort_by(|a, b| key(a).cmp(&key(b)));
+            windows.dedup_by(|a, b| key(a) == key(b));
+        }
 
-                // X11 Exact
-                if let Some(window_props) = &node.window_properties {
-                    if let Some(class) = &window_props.class {
-                        exact_name = Some(class);
+        let matched: Vec<(&str, Option<&String>, (usize, String))> = windows
+            .iter()
+            .map(|node| {
+                let generic_name = node.name.as_ref();
+                let exact_name = match exact_name(node) {
+                    Some(exact_name) => exact_name.as_str(),
+                    None => {
+                        error!(
+                            "No exact name found for window with title={:?}",
+                            generic_name
+                        );
+                        ""
                     }
-                }
-
-                (exact_name, node.name.clone())
+                };
+                let icon = self.config.fetch_icon_with_priority(&window_fields(node));
+                (exact_name, generic_name, icon)
             })
             .collect();
 
-        if self.deduplicate {
-            window_names = window_names
-                .into_iter()
-                .collect::<BTreeSet<(Option<&String>, Option<String>)>>()
-                .into_iter()
-                .collect();
+        if let Some(unmatched) = &mut self.unmatched {
+            for (node, (_, _, (priority, _))) in windows.iter().zip(&matched) {
+                // The fallback has the lowest priority
+                if *priority != usize::MAX {
+                    continue;
+                }
+                let class = node
+                    .window_properties
+                    .as_ref()
+                    .and_then(|p| p.class.as_deref());
+                match unmatched.record(node.app_id.as_deref(), class, node.name.as_deref()) {
+                    Ok(true) => info!("Recorded unmatched window {:?}", exact_name(node)),
+                    Ok(false) => {}
+                    Err(e) => error!("Could not record unmatched window: {e}"),
+                }
+            }
         }
 
-        let mut icons: Vec<String> = window_names
-            .into_iter()
-            .map(|(exact_name, generic_name)| {
-                if let Some(exact_name) = exact_name {
-                    self.config
-                        .fetch_icon(exact_name, generic_name.as_ref())
-                        .to_string()
-                } else {
-                    error!(
-                        "No exact name found for window with title={:?}",
-                        generic_name
-                    );
-                    self.config
-                        .fetch_icon(&String::new(), generic_name.as_ref())
-                        .to_string()
-                }
-            })
-            .filter(|icon| !icon.is_empty())
-            // Overwrite right to left characters: https://www.unicode.org/versions/Unicode12.0.0/UnicodeStandard-12.0.pdf#G26.16327
-            .map(|icon| format!("\u{202D}{icon}\u{202C}"))
-            .collect();
+        let mut icons: Vec<(usize, String)> = match self.config.combine(&matched) {
+            // Not from a rule, so it doesn't get the style of one
+            Some(value) => vec![(usize::MAX, value.clone())],
+            None => matched
+                .into_iter()
+                .map(|(_, _, (priority, icon))| {
+                    (priority, self.config.display_value(priority, &icon))
+                })
+                .collect(),
+        };
+        icons.retain(|(_, icon)| !icon.is_empty());
 
         let name = match &workspace.name {
             Some(name) => name,
@@ -215,63 +504,398 @@ impl Sworkstyle {
             }
         };
 
-        let index = match workspace.num {
-            Some(num) => num,
-            None => return Err(format!("Could not fetch index for: {}", name).into()),
-        };
-
         if self.deduplicate {
-            icons.dedup();
+            icons.dedup_by(|a, b| a.1 == b.1);
         }
 
         let delim = self.config.separator.as_deref().unwrap_or(" ");
 
-        let mut icons = icons.join(delim);
+        // Markup is added after truncating so it doesn't count towards the width
+        let markup = self.config.markup;
+        let text = |s: &str| {
+            if markup {
+                escape_markup(s)
+            } else {
+                s.to_string()
+            }
+        };
+        let icons = truncate_icons(icons, delim, self.config.max_icons, self.config.max_width);
+        let delim = &text(delim);
 
-        if icons.len() > 0 {
-            icons.push_str(" ")
+        let mut icons = icons
+            .into_iter()
+            .map(|(priority, icon)| {
+                // Overwrite right to left characters: https://www.unicode.org/versions/Unicode12.0.0/UnicodeStandard-12.0.pdf#G26.16327
+                let icon = format!("\u{202D}{}\u{202C}", text(&icon));
+                match self.config.styles.get(priority) {
+                    Some(style) if markup => style_span(&icon, style),
+                    _ => icon,
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(delim);
+
+        if let Some(style) = self.config.workspace_style(workspace.num) {
+            let value = text(&style.value);
+            if !icons.is_empty() {
+                icons = format!("{value}{delim}{icons}");
+            } else if style.show_empty {
+                icons = value;
+            }
         }
 
-        let new_name = if icons.len() > 0 {
-            format!("{}: {}", index, icons)
-        } else if let Some(num) = workspace.num {
-            format!("{}", num)
-        } else {
-            error!("Could not fetch workspace num for: {:?}", workspace.name);
-            " ".to_string()
+        if !icons.is_empty() {
+            icons.push(' ')
+        }
+
+        let new_name = match (workspace.num, self.labels.get(&workspace.id)) {
+            (Some(num), Some(label)) if icons.is_empty() => format!("{}: {} ", num, text(label)),
+            (Some(num), Some(label)) => format!("{}: {}{}{}", num, text(label), delim, icons),
+            (Some(num), None) if icons.is_empty() => format!("{}", num),
+            (Some(num), None) => format!("{}: {}", num, icons),
+            // Workspaces without a number that got renamed by someone else use their label
+            // in place of the number
+            (None, Some(label)) if icons.is_empty() => text(label),
+            (None, Some(label)) => format!("{}: {}", text(label), icons),
+            // The name is all there is to tell the workspace apart
+            (None, None) => {
+                debug!("Not renaming workspace \"{name}\" as it has no number");
+                return Ok(());
+            }
         };
 
         if *name != new_name {
             debug!("rename workspace \"{}\" to \"{}\"", name, new_name);
 
-            conn.run_command(format!("rename workspace \"{}\" to \"{}\"", name, new_name))
+            let outcomes = conn
+                .run_command(format!(
+                    "rename workspace \"{}\" to \"{}\"",
+                    escape_quotes(name),
+                    escape_quotes(&new_name)
+                ))
                 .await?;
+            // A rejected rename gets no rename event that would take its name off the queue
+            for outcome in outcomes {
+                outcome?;
+            }
+            let pending = self.pending_names.entry(workspace.id).or_default();
+            pending.push_back(new_name);
+            if pending.len() > MAX_PENDING_NAMES {
+                pending.pop_front();
+            }
         }
 
-        return Ok(());
+        Ok(())
+    }
+}
+
+/// Escape a string so it can be used within double quotes in a sway command
+fn escape_quotes(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escape text so it is shown as is with pango markup enabled
+fn escape_markup(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+}
+
+/// Wrap escaped text in a pango span with the color, font and size of a rule
+fn style_span(text: &str, style: &RuleStyle) -> String {
+    let attributes: String = [
+        ("foreground", &style.color),
+        ("font_family", &style.font),
+        ("size", &style.size.as_deref().map(pango_size)),
+    ]
+    .iter()
+    .filter_map(|(name, value)| {
+        value
+            .as_ref()
+            .map(|value| format!(" {name}='{}'", escape_markup(value)))
+    })
+    .collect();
+
+    if attributes.is_empty() {
+        text.to_string()
+    } else {
+        format!("<span{attributes}>{text}</span>")
+    }
+}
+
+/// Write sizes in points like `12pt` in pango units, as pango only knows points since 1.50
+fn pango_size(size: &str) -> String {
+    match size
+        .strip_suffix("pt")
+        .map(|points| points.trim().parse::<f64>())
+    {
+        Some(Ok(points)) => format!("{}", (points * 1024.0).round() as i64),
+        _ => size.to_string(),
+    }
+}
+
+/// Drop the icons with the lowest priority until both `max_icons` and `max_width` are satisfied.
+/// The order of the remaining icons is kept and an overflow marker (`+{n}`) with the lowest
+/// priority is appended when any icons got dropped.
+fn truncate_icons(
+    mut icons: Vec<(usize, String)>,
+    delim: &str,
+    max_icons: Option<usize>,
+    max_width: Option<usize>,
+) -> Vec<(usize, String)> {
+    let total = icons.len();
+    let width = |icons: &Vec<(usize, String)>| {
+        let mut names: Vec<&str> = icons.iter().map(|(_, icon)| icon.as_str()).collect();
+        let overflow = format!("+{}", total - icons.len());
+        if icons.len() < total {
+            names.push(&overflow);
+        }
+        names.join(delim).width()
+    };
+    let too_many = |icons: &Vec<(usize, String)>| {
+        max_icons.is_some_and(|max| icons.len() > max)
+            || max_width.is_some_and(|max| width(icons) > max)
+    };
+
+    while !icons.is_empty() && too_many(&icons) {
+        // Remove the last icon with the lowest priority
+        if let Some((i, _)) = icons
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, (priority, _))| (*priority, *i))
+        {
+            icons.remove(i);
+        }
+    }
+
+    let dropped = total - icons.len();
+    if dropped > 0 {
+        icons.push((usize::MAX, format!("+{dropped}")));
     }
+    icons
 }
 
-fn get_workspaces_recurse<'a>(node: &'a Node, workspaces: &mut Vec<&'a Node>) {
-    if node.node_type == NodeType::Workspace && node.name != Some("__i3_scratch".to_string()) {
-        workspaces.push(node);
+/// Get the exact name of a window, being the X11 class or otherwise the Wayland app_id
+fn exact_name(node: &Node) -> Option<&String> {
+    let mut exact_name: Option<&String> = None;
+
+    // Wayland Exact app
+    if let Some(app_id) = &node.app_id {
+        exact_name = Some(app_id);
+    }
+
+    // X11 Exact
+    if let Some(window_props) = &node.window_properties {
+        if let Some(class) = &window_props.class {
+            exact_name = Some(class);
+        }
+    }
+
+    exact_name
+}
+
+/// Get the fields of a window that can be used in values
+fn window_fields(node: &Node) -> Window<'_> {
+    Window {
+        app_id: node.app_id.as_deref(),
+        class: node
+            .window_properties
+            .as_ref()
+            .and_then(|p| p.class.as_deref()),
+        title: node.name.as_deref(),
+    }
+}
+
+/// Rescursively add workspaces that are not excluded, `is_excluded` gets the workspace and its output name
+fn get_workspaces_recurse<'a>(
+    node: &'a Node,
+    output: Option<&'a str>,
+    workspaces: &mut Vec<&'a Node>,
+    is_excluded: &dyn Fn(&Node, Option<&str>) -> bool,
+) {
+    if node.node_type == NodeType::Workspace {
+        if !is_excluded(node, output) {
+            workspaces.push(node);
+        }
         return;
     }
 
+    let output = match node.node_type {
+        NodeType::Output => node.name.as_deref(),
+        _ => output,
+    };
+
     for child in node.nodes.iter() {
-        get_workspaces_recurse(child, workspaces)
+        get_workspaces_recurse(child, output, workspaces, is_excluded)
     }
 }
 
+/// Get the app id, class and title of the window with the given con_id from the running sway
+pub async fn find_window(
+    con_id: i64,
+) -> Result<Option<(Option<String>, Option<String>, Option<String>)>, SworkstyleError> {
+    let tree = Connection::new().await?.get_tree().await?;
+    let mut windows = vec![];
+    get_windows(&tree, &mut windows);
+
+    Ok(windows.into_iter().find(|w| w.id == con_id).map(|w| {
+        let fields = window_fields(w);
+        (
+            fields.app_id.map(String::from),
+            fields.class.map(String::from),
+            fields.title.map(String::from),
+        )
+    }))
+}
+
 /// Rescursively add nodes with node type floatingCon and con to windows
 fn get_windows<'a>(node: &'a Node, windows: &mut Vec<&'a Node>) {
-    if node.node_type == NodeType::FloatingCon || node.node_type == NodeType::Con {
-        if let Some(_) = node.name {
-            windows.push(node)
-        }
+    if (node.node_type == NodeType::FloatingCon || node.node_type == NodeType::Con)
+        && node.name.is_some()
+    {
+        windows.push(node)
     };
 
     for node in node.nodes.iter().chain(node.floating_nodes.iter()) {
         get_windows(node, windows);
     }
 }
+
+#[test]
+fn test_truncate_icons() {
+    let truncate_icons = |icons, delim, max_icons, max_width| {
+        truncate_icons(icons, delim, max_icons, max_width)
+            .into_iter()
+            .map(|(_, icon)| icon)
+            .collect::<Vec<String>>()
+    };
+    let icons = vec![
+        (3, "a".to_string()),
+        (usize::MAX, "b".to_string()),
+        (0, "c".to_string()),
+        (1, "d".to_string()),
+    ];
+
+    assert_eq!(
+        truncate_icons(icons.clone(), " ", None, None),
+        vec!["a", "b", "c", "d"]
+    );
+    assert_eq!(
+        truncate_icons(icons.clone(), " ", Some(2), None),
+        vec!["c", "d", "+2"]
+    );
+    // "a b c d" is 7 wide and "a c d +1" is 8 wide
+    assert_eq!(
+        truncate_icons(icons.clone(), " ", None, Some(7)),
+        vec!["a", "b", "c", "d"]
+    );
+    assert_eq!(
+        truncate_icons(icons.clone(), " ", None, Some(6)),
+        vec!["c", "d", "+2"]
+    );
+    assert_eq!(truncate_icons(icons, " ", None, Some(5)), vec!["c", "+3"]);
+}
+
+#[test]
+fn test_style_span() {
+    let style = RuleStyle {
+        color: Some("#ff0000".to_string()),
+        size: Some("12pt".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        style_span(&escape_markup("a & <b>"), &style),
+        "<span foreground='#ff0000' size='12288'>a &amp; &lt;b&gt;</span>"
+    );
+    assert_eq!(pango_size("10.5pt"), "10752");
+    assert_eq!(pango_size("large"), "large");
+    assert_eq!(style_span("a", &RuleStyle::default()), "a");
+}
+
+#[test]
+fn test_handle_rename() {
+    let config = Config::from("on_foreign_rename = 'back_off'\n[matching]");
+    let mut sworkstyle = Sworkstyle::with_config(config, &[] as &[PathBuf], false);
+    sworkstyle
+        .pending_names
+        .insert(1, VecDeque::from(["1: a ".to_string()]));
+
+    // Our own rename
+    assert!(!sworkstyle.handle_rename(1, Some(1), "1: a ", None));
+    assert!(!sworkstyle.is_workspace_excluded(1, Some(1), "1: a ", None));
+
+    assert!(!sworkstyle.handle_rename(1, Some(1), "1: mine", None));
+    assert!(sworkstyle.is_workspace_excluded(1, Some(1), "1: mine", None));
+    assert!(!sworkstyle.is_workspace_excluded(2, Some(2), "2", None));
+
+    sworkstyle.config.on_foreign_rename = RenamePolicy::Respect;
+    assert!(sworkstyle.handle_rename(3, None, "notes", None));
+    assert_eq!(sworkstyle.labels.get(&3).map(|l| l.as_str()), Some("notes"));
+    assert!(sworkstyle.handle_rename(2, Some(2), "2: todo", None));
+    assert_eq!(sworkstyle.labels.get(&2).map(|l| l.as_str()), Some("todo"));
+
+    // Excluded workspaces keep any name
+    sworkstyle.config.exclude_outputs = vec!["HDMI-A-1".to_string()];
+    assert!(!sworkstyle.handle_rename(4, Some(4), "4: tv", Some("HDMI-A-1")));
+    assert!(!sworkstyle.labels.contains_key(&4));
+
+    // Waits for the next window event
+    sworkstyle.config.on_foreign_rename = RenamePolicy::Overwrite;
+    assert!(!sworkstyle.handle_rename(2, Some(2), "2: other", None));
+}
+
+#[test]
+fn test_needs_reload() {
+    let dir = util::TestDir::new("needs-reload");
+    let Some(source) = ConfigSource::new(&[dir.join("config.toml")]) else {
+        return;
+    };
+    let drop_in = dir.join("conf.d/10-extra.toml");
+
+    assert!(source.needs_reload(&drop_in, EventMask::CLOSE_WRITE));
+    assert!(source.needs_reload(&drop_in, EventMask::DELETE));
+    assert!(source.needs_reload(&drop_in, EventMask::MOVED_FROM));
+    assert!(!source.needs_reload(&source.paths[0], EventMask::DELETE));
+    assert!(!source.needs_reload(&source.paths[0], EventMask::CREATE));
+}
+
+#[test]
+fn test_needs_reload_for_link() {
+    use std::os::unix::fs::symlink;
+
+    let dir = util::TestDir::new("needs-reload-for-link");
+    let dotfile = dir.write("dotfiles.toml", "[matching]");
+    dir.write("new.toml", "");
+    symlink(&dotfile, dir.join("symlink.toml")).unwrap();
+    fs::hard_link(&dotfile, dir.join("hard_link.toml")).unwrap();
+
+    // Without inotify there is nothing to test
+    let Some(source) = ConfigSource::new(&[dir.join("config.toml")]) else {
+        return;
+    };
+    let reloads = ["new.toml", "symlink.toml", "hard_link.toml"]
+        .map(|file| source.needs_reload(&dir.join(file), EventMask::CREATE));
+    assert_eq!(reloads, [false, true, true]);
+}
+
+#[test]
+fn test_watch_symlinked_config() {
+    use std::os::unix::fs::symlink;
+
+    let dir = util::TestDir::new("watch-symlinked-config");
+    let dotfile = dir.write("dotfiles/config.toml", "[matching]");
+    fs::create_dir_all(dir.join("config")).unwrap();
+    symlink(&dotfile, dir.join("config/config.toml")).unwrap();
+
+    // Without inotify there is nothing to test
+    let Some(source) = ConfigSource::new(&[dir.join("config/config.toml")]) else {
+        return;
+    };
+    let target = fs::canonicalize(&dotfile).unwrap();
+    assert!(source.is_config_file(&target), "{:?}", source.files);
+    assert!(source
+        .dirs
+        .values()
+        .any(|d| Some(d.as_path()) == target.parent()));
+}
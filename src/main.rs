@@ -1,5 +1,10 @@
 use std::process;
-use sworkstyle::Sworkstyle;
+use sworkstyle::{
+    config::{self, add_rule, check_config_files, lint_config, Config, Match, RuleType, Severity},
+    find_window,
+    unmatched::UnmatchedWindows,
+    Sworkstyle,
+};
 
 use fslock::LockFile;
 use log::{debug, error};
@@ -11,22 +16,58 @@ use log::LevelFilter;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+pub enum Command {
+    /// Rename workspaces
+    Run,
+    /// Check the config for problems
+    Check,
+    /// Find shadowed, duplicate and catch-all rules
+    Lint,
+    /// Show which rule matches a window
+    Explain(Window),
+    /// Add a rule to the config file
+    Add {
+        pattern: Option<String>,
+        icon: Option<String>,
+        rule_type: Option<RuleType>,
+    },
+    /// List windows recorded in learn mode
+    Unmatched {
+        /// Print rules that can be pasted into the config
+        stubs: bool,
+    },
+}
+
+/// A window given on the command line
+#[derive(Default)]
+pub struct Window {
+    pub app_id: Option<String>,
+    pub class: Option<String>,
+    pub title: Option<String>,
+    /// Window of the running sway session
+    pub con_id: Option<i64>,
+}
+
 pub struct Args {
+    pub command: Command,
     pub log_level: LevelFilter,
-    pub config_path: Option<PathBuf>,
+    /// Config files layered on top of each other, earlier files take precedence
+    pub config_paths: Vec<PathBuf>,
     pub deduplicate: bool,
-}
-
-/// Get the xdg default config path
-fn default_config_path() -> Option<PathBuf> {
-    Some(dirs::config_dir()?.join("sworkstyle/config.toml"))
+    pub strict: bool,
+    pub print_config: bool,
+    pub learn: bool,
 }
 
 impl Args {
     pub fn from_cli() -> Args {
+        let mut command = Command::Run;
         let mut log_level = LevelFilter::Warn;
-        let mut config_path = default_config_path();
+        let mut config_paths = config::default_config_paths();
         let mut deduplicate = false;
+        let mut strict = false;
+        let mut print_config = false;
+        let mut learn = false;
 
         let mut args = env::args().skip(1);
         while let Some(arg) = args.next() {
@@ -39,6 +80,36 @@ Config can be found in $HOME/.config/sworkstyle
 
 SYNOPSIS
     sworkstyle [FLAGS]
+    sworkstyle check [FLAGS] [<file>]
+    sworkstyle lint [FLAGS] [<file>]
+    sworkstyle explain [FLAGS] [--app-id <app_id>] [--class <class>] [--title <title>]
+    sworkstyle explain [FLAGS] --con-id <con_id>
+    sworkstyle add [FLAGS] <pattern> <icon> [--generic|--exact]
+    sworkstyle unmatched [FLAGS] [--stubs]
+
+COMMANDS
+    check [<file>]
+        Check the config for problems and print their locations.
+        Exits with a non-zero code when any problem has been found.
+
+    lint [<file>]
+        Find rules that can never match, duplicate the default rules
+        or only differ in case. Exits with a non-zero code when any rule has been found,
+        rules overriding a default rule are only reported as info.
+
+    explain
+        Print every rule tried for a window, which one matched, where it comes from
+        and the resulting icon. The window is either given by its app id, class and title
+        or by the con_id of a window in the running sway session.
+
+    add <pattern> <icon> [--generic|--exact]
+        Add a rule to the end of the matching table of the config file, keeping its comments.
+        The file is created when it doesn't exist and a running sworkstyle reloads it.
+        Without --generic or --exact a /regex/ matches the title and other patterns the app.
+
+    unmatched [--stubs]
+        List the windows recorded with --learn. With --stubs rules are printed instead,
+        ready to be pasted into the matching table. Windows that match a rule by now are left out.
 
 FLAGS
     -h, --help
@@ -51,10 +122,21 @@ FLAGS
         Either \"error\", \"warn\", \"info\", \"debug\", \"off\". Uses \"warn\" by default
         
     -c, --config <file>
-        Specifies the config file to use. Uses \"`XDG_CONFIG_HOME`/sworkstyle/config\" by default
+        Specifies the config file to use. Uses \"`XDG_CONFIG_HOME`/sworkstyle/config.toml\" by default,
+        layered on top of \"sworkstyle/config.toml\" in `XDG_CONFIG_DIRS`. The \"conf.d\" drop-in
+        files are only read next to these default files.
 
     -d, --deduplicate
         Deduplicate the same icons in your workspace
+
+    -s, --strict
+        Exit when the config file is invalid instead of using the default config
+
+    --learn
+        Record every distinct window without a matching rule in \"`XDG_STATE_HOME`/sworkstyle/unmatched.toml\"
+
+    --print-config
+        Print the effective config, including includes and the default rules, as toml and exit
         "
                     );
                     process::exit(0);
@@ -88,7 +170,7 @@ FLAGS
                             eprintln!("Config file does not exist or couldn't be accessed");
                             process::exit(1);
                         }
-                        config_path = Some(path);
+                        config_paths = vec![path];
                     } else {
                         eprintln!("No path given");
                         process::exit(1);
@@ -97,6 +179,85 @@ FLAGS
                 "-d" | "--deduplicate" => {
                     deduplicate = true;
                 }
+                "-s" | "--strict" => {
+                    strict = true;
+                }
+                "--print-config" => {
+                    print_config = true;
+                }
+                "--learn" => {
+                    learn = true;
+                }
+                "check" if matches!(command, Command::Run) => {
+                    command = Command::Check;
+                }
+                "lint" if matches!(command, Command::Run) => {
+                    command = Command::Lint;
+                }
+                "explain" if matches!(command, Command::Run) => {
+                    command = Command::Explain(Window::default());
+                }
+                "--app-id" | "--class" | "--title" | "--con-id"
+                    if matches!(command, Command::Explain(_)) =>
+                {
+                    let Some(value) = args.next() else {
+                        eprintln!("No value given for {arg}");
+                        process::exit(1);
+                    };
+                    let Command::Explain(window) = &mut command else {
+                        unreachable!()
+                    };
+                    match &arg[..] {
+                        "--app-id" => window.app_id = Some(value),
+                        "--class" => window.class = Some(value),
+                        "--title" => window.title = Some(value),
+                        _ => match value.parse() {
+                            Ok(con_id) => window.con_id = Some(con_id),
+                            Err(_) => {
+                                eprintln!("Invalid con_id: {value}");
+                                process::exit(1);
+                            }
+                        },
+                    }
+                }
+                "add" if matches!(command, Command::Run) => {
+                    command = Command::Add {
+                        pattern: None,
+                        icon: None,
+                        rule_type: None,
+                    };
+                }
+                "unmatched" if matches!(command, Command::Run) => {
+                    command = Command::Unmatched { stubs: false };
+                }
+                "--stubs" if matches!(command, Command::Unmatched { .. }) => {
+                    command = Command::Unmatched { stubs: true };
+                }
+                "--generic" | "--exact" if matches!(command, Command::Add { .. }) => {
+                    if let Command::Add { rule_type, .. } = &mut command {
+                        *rule_type = Some(match &arg[..] {
+                            "--generic" => RuleType::Generic,
+                            _ => RuleType::Exact,
+                        });
+                    }
+                }
+                value
+                    if matches!(command, Command::Add { icon: None, .. })
+                        && !value.starts_with('-') =>
+                {
+                    if let Command::Add { pattern, icon, .. } = &mut command {
+                        if pattern.is_none() {
+                            *pattern = Some(value.to_string());
+                        } else {
+                            *icon = Some(value.to_string());
+                        }
+                    }
+                }
+                path if matches!(command, Command::Check | Command::Lint)
+                    && !path.starts_with('-') =>
+                {
+                    config_paths = vec![PathBuf::from(path)];
+                }
                 _ => {
                     eprintln!("Did not recognize \"{}\" as an option", arg);
                     process::exit(1);
@@ -104,13 +265,214 @@ FLAGS
             }
         }
 
-        return Args {
+        if let Command::Add { icon: None, .. } = command {
+            eprintln!("No pattern and icon given to add");
+            process::exit(1);
+        }
+
+        Args {
+            command,
             log_level,
-            config_path,
+            config_paths,
             deduplicate,
+            strict,
+            print_config,
+            learn,
+        }
+    }
+}
+/// Print all problems in the config, returns the exit code
+fn check(config_paths: &[PathBuf]) -> i32 {
+    if !config_paths.iter().any(|p| p.exists()) {
+        eprintln!("No config file found");
+        return 1;
+    }
+
+    let errors = check_config_files(config_paths);
+    for e in &errors {
+        eprintln!("{e}");
+    }
+
+    if errors.is_empty() {
+        println!("Config is valid");
+        0
+    } else {
+        1
+    }
+}
+
+/// Print all rules that are shadowed or duplicated, returns the exit code
+fn lint(config_paths: &[PathBuf]) -> i32 {
+    let config = match Config::try_with_layers(config_paths) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+
+    let warnings = lint_config(&config);
+    for (severity, w) in &warnings {
+        eprintln!("{severity}: {w}");
+    }
+
+    // Informational findings like overridden default rules are usually intended
+    if warnings.is_empty() {
+        println!("No problems found");
+        0
+    } else if warnings.iter().all(|(s, _)| *s == Severity::Info) {
+        0
+    } else {
+        1
+    }
+}
+
+/// Print every rule tried for a window and the resulting icon, returns the exit code
+fn explain(config_paths: &[PathBuf], window: Window) -> i32 {
+    let mut config = match Config::try_with_layers(config_paths) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+    config.load_desktop_entries();
+
+    let window = match window.con_id {
+        Some(con_id) => match async_io::block_on(find_window(con_id)) {
+            Ok(Some((app_id, class, title))) => Window {
+                app_id,
+                class,
+                title,
+                con_id: Some(con_id),
+            },
+            Ok(None) => {
+                eprintln!("No window found with con_id {con_id}");
+                return 1;
+            }
+            Err(e) => {
+                eprintln!("Could not get the window from sway: {e}");
+                return 1;
+            }
+        },
+        None => window,
+    };
+    let fields = config::Window {
+        app_id: window.app_id.as_deref(),
+        class: window.class.as_deref(),
+        title: window.title.as_deref(),
+    };
+    let exact_name = fields.exact_name().unwrap_or_default();
+    let generic_name = window.title.as_ref();
+
+    println!(
+        "Window with app \"{exact_name}\" and title \"{}\"",
+        generic_name.map(|t| t.as_str()).unwrap_or("-")
+    );
+    if config.is_ignored(exact_name, generic_name) {
+        println!("Window is ignored and not part of the workspace name");
+        return 0;
+    }
+
+    let tried = config.explain(exact_name, generic_name);
+    for (i, (m, source, matched)) in tried.iter().enumerate() {
+        let kind = match m {
+            Match::Generic { .. } => "generic",
+            Match::Exact { .. } => "exact",
         };
+        println!(
+            "{:>4}. {} '{}' from {source}: {}",
+            i + 1,
+            kind,
+            m.pattern_key(),
+            if *matched { "matched" } else { "no match" }
+        );
+    }
+
+    // Rules are tried in order, so the matching rule is at the last index
+    let (index, icon) = match tried.last() {
+        Some((_, source, true)) => {
+            let index = tried.len() - 1;
+            let icon = config.render_value(index, &fields);
+            println!("Icon: {icon} (from {source})");
+            (index, icon)
+        }
+        _ => match config.fetch_fallback(&fields) {
+            (usize::MAX, icon) => {
+                println!("Icon: {icon} (fallback chain)");
+                (usize::MAX, icon)
+            }
+            (index, icon) => {
+                println!(
+                    "Icon: {icon} (from '{}' through the desktop entry)",
+                    config.matchings[index].pattern_key()
+                );
+                (index, icon)
+            }
+        },
+    };
+    let shown = config.display_value(index, &icon);
+    if shown != icon {
+        println!("Shown as: {shown}");
+    }
+    0
+}
+
+/// Add a rule to the user config, returns the exit code
+fn add(config_paths: &[PathBuf], pattern: &str, icon: &str, rule_type: Option<RuleType>) -> i32 {
+    let Some(path) = config_paths.first() else {
+        eprintln!("No config file found");
+        return 1;
+    };
+
+    match add_rule(path, pattern, icon, rule_type) {
+        Ok(()) => {
+            println!("Added '{pattern}' to {}", path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
     }
 }
+
+/// Print the windows recorded in learn mode that are still unmatched, returns the exit code
+fn unmatched(config_paths: &[PathBuf], stubs: bool) -> i32 {
+    let Some(path) = UnmatchedWindows::default_path() else {
+        eprintln!("No state directory found");
+        return 1;
+    };
+    let unmatched = match UnmatchedWindows::load(&path) {
+        Ok(unmatched) => unmatched,
+        Err(e) => {
+            eprintln!("Could not read {}: {e}", path.display());
+            return 1;
+        }
+    };
+    let config = Config::with_layers(config_paths);
+
+    for window in unmatched.windows() {
+        let exact_name = window.exact_name().map(|n| n.as_str()).unwrap_or_default();
+        let tried = config.explain(exact_name, window.title.as_ref());
+        if tried.last().is_some_and(|(_, _, matched)| *matched) {
+            continue;
+        }
+
+        if stubs {
+            println!("{}", window.rule_stub());
+        } else {
+            println!(
+                "app_id: {:<24} class: {:<24} title: {}",
+                window.app_id.as_deref().unwrap_or("-"),
+                window.class.as_deref().unwrap_or("-"),
+                window.title.as_deref().unwrap_or("-"),
+            );
+        }
+    }
+    0
+}
+
 fn acquire_lock() {
     let mut file = match LockFile::open("/tmp/sworkstyle.lock") {
         Ok(f) => f,
@@ -119,7 +481,7 @@ fn acquire_lock() {
 
     let locked = file.try_lock().unwrap();
 
-    if locked == false {
+    if !locked {
         error!("Sworkstyle already running");
         process::exit(1)
     }
@@ -140,9 +502,62 @@ fn main() {
         .init()
         .expect("Could not load simple logger");
 
+    match args.command {
+        Command::Check => process::exit(check(&args.config_paths)),
+        Command::Lint => process::exit(lint(&args.config_paths)),
+        Command::Explain(window) => process::exit(explain(&args.config_paths, window)),
+        Command::Add {
+            pattern: Some(pattern),
+            icon: Some(icon),
+            rule_type,
+        } => process::exit(add(&args.config_paths, &pattern, &icon, rule_type)),
+        Command::Add { .. } => unreachable!(),
+        Command::Unmatched { stubs } => process::exit(unmatched(&args.config_paths, stubs)),
+        Command::Run => {}
+    }
+
+    if args.print_config {
+        // An invalid config is an error, printing the default config would hide it
+        match Config::try_with_layers(&args.config_paths) {
+            Ok(config) => print!("{}", config.to_toml()),
+            Err(e) => {
+                eprintln!("{e}");
+                process::exit(1)
+            }
+        }
+        process::exit(0)
+    }
+
     acquire_lock();
 
-    let app = Sworkstyle::new(args.config_path, args.deduplicate);
+    let app = if args.strict {
+        match Sworkstyle::try_with_layers(&args.config_paths, args.deduplicate) {
+            Ok(app) => app,
+            Err(e) => {
+                error!("{e}");
+                process::exit(1)
+            }
+        }
+    } else {
+        Sworkstyle::with_layers(&args.config_paths, args.deduplicate)
+    };
+    let app = if args.learn {
+        let unmatched = UnmatchedWindows::default_path()
+            .ok_or_else(|| "No state directory found".to_string())
+            .and_then(|path| {
+                UnmatchedWindows::load(&path)
+                    .map_err(|e| format!("Could not read {}: {e}", path.display()))
+            });
+        match unmatched {
+            Ok(unmatched) => app.with_learn_mode(unmatched),
+            Err(e) => {
+                error!("{e}");
+                process::exit(1)
+            }
+        }
+    } else {
+        app
+    };
     if let Err(e) = async_io::block_on(app.run()) {
         error!("{e}");
         process::exit(1)
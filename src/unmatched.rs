@@ -0,0 +1,171 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use toml::{Table, Value};
+
+/// A window for which no rule matched
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnmatchedWindow {
+    pub app_id: Option<String>,
+    pub class: Option<String>,
+    /// Title of the first time the window was seen
+    pub title: Option<String>,
+}
+
+impl UnmatchedWindow {
+    /// The name used for exact matching, X11 class takes precedence over the wayland app id
+    pub fn exact_name(&self) -> Option<&String> {
+        self.class.as_ref().or(self.app_id.as_ref())
+    }
+
+    /// A rule that can be pasted into the matching table
+    pub fn rule_stub(&self) -> String {
+        let title = self.title.as_deref().unwrap_or_default();
+        match self.exact_name() {
+            Some(name) if title.is_empty() => format!("{} = ''", quote(name)),
+            Some(name) => format!("{} = '' # {}", quote(name), title.replace('\n', " ")),
+            None => format!("{} = {{ type = 'generic', value = '' }}", quote(title)),
+        }
+    }
+}
+
+/// Distinct unmatched windows, persisted to a file so they can be turned into rules later
+pub struct UnmatchedWindows {
+    path: PathBuf,
+    windows: Vec<UnmatchedWindow>,
+}
+
+impl UnmatchedWindows {
+    /// Default file under the xdg state dir
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::state_dir().map(|dir| dir.join("sworkstyle/unmatched.toml"))
+    }
+
+    /// Load the recorded windows, a missing file has no windows
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<UnmatchedWindows> {
+        let path = path.as_ref().to_path_buf();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let table: Table =
+            toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let field = |w: &Value, key: &str| w.get(key).and_then(|v| v.as_str()).map(String::from);
+        let windows = table
+            .get("window")
+            .and_then(|w| w.as_array())
+            .map(|windows| {
+                windows
+                    .iter()
+                    .map(|w| UnmatchedWindow {
+                        app_id: field(w, "app_id"),
+                        class: field(w, "class"),
+                        title: field(w, "title"),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(UnmatchedWindows { path, windows })
+    }
+
+    pub fn windows(&self) -> &[UnmatchedWindow] {
+        &self.windows
+    }
+
+    /// Record a window once per app id and class, keeping the first title as sample. Windows
+    /// without either are recorded once too, as titles change too often to tell them apart.
+    /// Returns if the window was not recorded before.
+    pub fn record(
+        &mut self,
+        app_id: Option<&str>,
+        class: Option<&str>,
+        title: Option<&str>,
+    ) -> io::Result<bool> {
+        let known = self
+            .windows
+            .iter()
+            .any(|w| w.app_id.as_deref() == app_id && w.class.as_deref() == class);
+        if known {
+            return Ok(false);
+        }
+
+        self.windows.push(UnmatchedWindow {
+            app_id: app_id.map(String::from),
+            class: class.map(String::from),
+            title: title.map(String::from),
+        });
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let windows = self
+            .windows
+            .iter()
+            .map(|w| {
+                let mut table = Table::new();
+                for (key, value) in [
+                    ("app_id", &w.app_id),
+                    ("class", &w.class),
+                    ("title", &w.title),
+                ] {
+                    if let Some(value) = value {
+                        table.insert(key.to_string(), Value::from(value.as_str()));
+                    }
+                }
+                Value::Table(table)
+            })
+            .collect();
+        let mut root = Table::new();
+        root.insert("window".to_string(), Value::Array(windows));
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(
+            &self.path,
+            toml::to_string(&root).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        )
+    }
+}
+
+/// Quote a string as toml literal string when possible
+fn quote(s: &str) -> String {
+    if s.contains('\'') || s.contains('\n') {
+        Value::from(s).to_string()
+    } else {
+        format!("'{s}'")
+    }
+}
+
+#[test]
+fn test_unmatched_windows() {
+    let dir = crate::util::TestDir::new("unmatched");
+    let path = dir.join("state/unmatched.toml");
+
+    let mut unmatched = UnmatchedWindows::load(&path).unwrap();
+    assert!(unmatched.windows().is_empty());
+    assert!(unmatched
+        .record(Some("foot"), None, Some("~/code"))
+        .unwrap());
+    assert!(!unmatched.record(Some("foot"), None, Some("vim")).unwrap());
+    assert!(unmatched.record(None, Some("Steam"), None).unwrap());
+    assert!(unmatched.record(None, None, Some("it's a title")).unwrap());
+    assert!(!unmatched.record(None, None, Some("another title")).unwrap());
+
+    let unmatched = UnmatchedWindows::load(&path).unwrap();
+    let stubs: Vec<String> = unmatched.windows().iter().map(|w| w.rule_stub()).collect();
+    assert_eq!(
+        stubs,
+        [
+            "'foot' = '' # ~/code",
+            "'Steam' = ''",
+            "\"it's a title\" = { type = 'generic', value = '' }"
+        ]
+    );
+}
@@ -1,4 +1,11 @@
-use std::fmt::Display;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::{debug, log, Level};
 
 /// Map an option to a printable string
 pub fn prettify_option<T: Display>(option: Option<T>) -> String {
@@ -7,3 +14,99 @@ pub fn prettify_option<T: Display>(option: Option<T>) -> String {
         None => "-".to_string(),
     }
 }
+
+/// Logs messages with the same key at most once per interval, repeats in between are
+/// logged at debug level and counted
+pub struct RateLimitedLog {
+    interval: Duration,
+    /// Last time a key got logged and the amount of repeats suppressed since
+    reported: Mutex<BTreeMap<String, (Instant, usize)>>,
+}
+
+impl RateLimitedLog {
+    pub const fn new(interval: Duration) -> RateLimitedLog {
+        RateLimitedLog {
+            interval,
+            reported: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Check if a message with `key` should be logged, giving the amount of suppressed repeats
+    pub fn check(&self, key: &str) -> Option<usize> {
+        let mut reported = self.reported.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        match reported.get_mut(key) {
+            Some((last, suppressed)) if now.duration_since(*last) < self.interval => {
+                *suppressed += 1;
+                None
+            }
+            Some((last, suppressed)) => {
+                *last = now;
+                Some(std::mem::take(suppressed))
+            }
+            None => {
+                reported.insert(key.to_string(), (now, 0));
+                Some(0)
+            }
+        }
+    }
+
+    pub fn log(&self, level: Level, key: &str, message: &str) {
+        match self.check(key) {
+            Some(0) => log!(level, "{message}"),
+            Some(n) => log!(level, "{message} ({n} repeats suppressed)"),
+            None => debug!("{message}"),
+        }
+    }
+}
+
+/// A directory for the files of a test, removed again when dropped
+#[cfg(test)]
+pub struct TestDir {
+    pub path: std::path::PathBuf,
+}
+
+#[cfg(test)]
+impl TestDir {
+    /// Create an empty directory for the test `name`, unique to this process
+    pub fn new(name: &str) -> TestDir {
+        let path = std::env::temp_dir().join(format!("sworkstyle-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("Could not create test dir");
+        TestDir { path }
+    }
+
+    pub fn join<P: AsRef<std::path::Path>>(&self, path: P) -> std::path::PathBuf {
+        self.path.join(path)
+    }
+
+    /// Write a file in the directory, creating the directories it is in
+    pub fn write<P: AsRef<std::path::Path>>(&self, path: P, content: &str) -> std::path::PathBuf {
+        let path = self.join(path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).expect("Could not create test dir");
+        }
+        std::fs::write(&path, content).expect("Could not write test file");
+        path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+#[test]
+fn test_rate_limited_log() {
+    let log = RateLimitedLog::new(Duration::from_secs(3600));
+    assert_eq!(log.check("firefox"), Some(0));
+    assert_eq!(log.check("firefox"), None);
+    assert_eq!(log.check("firefox"), None);
+    assert_eq!(log.check("foot"), Some(0));
+
+    let log = RateLimitedLog::new(Duration::ZERO);
+    assert_eq!(log.check("firefox"), Some(0));
+    assert_eq!(log.check("firefox"), Some(0));
+}